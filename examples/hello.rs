@@ -1,13 +1,21 @@
 use reduction_react::ReactorBuilder;
 
+// publish `hello.json` with the crate's binary, then serve it with
+// `reduction_react serve <releases dir>`
+
 fn main() {
     let reactor = ReactorBuilder::new()
         .name("hello")
         .version("1.0.4")
         .publishing_url("http://127.0.0.1:8080/hello.json")
         .finish();
-    if let Err(e) = reactor.oneclick() {
-        println!("failed to check updates: {}", e);
+    match reactor {
+        Ok(reactor) => {
+            if let Err(e) = reactor.oneclick() {
+                println!("failed to check updates: {}", e);
+            }
+        }
+        Err(e) => println!("failed to set up updater: {}", e),
     }
 
     println!("hello from 1.0.4");
@@ -0,0 +1,707 @@
+use std::{fmt::Display, fs, io::Read, path::Path};
+
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+// how a downloaded artifact is packed. when the manifest does not name it, the
+// format is detected from the first bytes of the download.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    #[serde(rename = "tar.xz")]
+    TarXz,
+    #[serde(rename = "tar.zst")]
+    TarZst,
+    // the download is the new executable itself
+    #[serde(rename = "raw")]
+    Raw,
+}
+
+impl ArchiveFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::TarXz => "tar.xz",
+            ArchiveFormat::TarZst => "tar.zst",
+            ArchiveFormat::Raw => "raw",
+        }
+    }
+
+    pub fn detect(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut magic = Vec::with_capacity(6);
+        fs::File::open(path)
+            .and_then(|file| file.take(6).read_to_end(&mut magic))
+            .map_err(|err| Error::CommonFileError {
+                message: format!("failed to read downloaded file `{:?}`", path),
+                source: err,
+            })?;
+        Ok(Self::from_magic(&magic))
+    }
+
+    // the format named by `name`, or by the extension of a file name. raw
+    // executables have no extension of their own.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name == "raw" {
+            return Some(ArchiveFormat::Raw);
+        }
+        [
+            (ArchiveFormat::Zip, "zip"),
+            (ArchiveFormat::TarGz, "tgz"),
+            (ArchiveFormat::TarXz, "txz"),
+            (ArchiveFormat::TarZst, "tzst"),
+        ]
+        .into_iter()
+        .find(|(format, short_name)| {
+            [format.name(), short_name]
+                .iter()
+                .any(|ext| name == *ext || name.ends_with(&format!(".{}", ext)))
+        })
+        .map(|(format, _)| format)
+    }
+
+    fn from_magic(magic: &[u8]) -> Self {
+        if magic.starts_with(b"PK\x03\x04") || magic.starts_with(b"PK\x05\x06") {
+            ArchiveFormat::Zip
+        } else if magic.starts_with(&[0x1f, 0x8b]) {
+            ArchiveFormat::TarGz
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            ArchiveFormat::TarXz
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            ArchiveFormat::TarZst
+        } else {
+            ArchiveFormat::Raw
+        }
+    }
+}
+
+impl Display for ArchiveFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// unpacks `src` into `dest`. a raw download is stored as `dest/raw_file_name`.
+pub fn extract(
+    format: ArchiveFormat,
+    src: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    raw_file_name: &str,
+) -> Result<(), Error> {
+    let src = src.as_ref();
+    let dest = dest.as_ref();
+    fs::create_dir_all(dest).map_err(|err| Error::CommonFileError {
+        message: format!("failed to create directories `{:?}`", dest),
+        source: err,
+    })?;
+    match format {
+        #[cfg(feature = "zip")]
+        ArchiveFormat::Zip => extract_zip(src, dest),
+        #[cfg(feature = "tar-gz")]
+        ArchiveFormat::TarGz => extract_tar(flate2::read::GzDecoder::new(open(src)?), dest),
+        #[cfg(feature = "tar-xz")]
+        ArchiveFormat::TarXz => extract_tar(xz2::read::XzDecoder::new(open(src)?), dest),
+        #[cfg(feature = "tar-zst")]
+        ArchiveFormat::TarZst => extract_tar(
+            zstd::stream::read::Decoder::new(open(src)?).map_err(|err| Error::CommonFileError {
+                message: format!("failed to decompress `{:?}`", src),
+                source: err,
+            })?,
+            dest,
+        ),
+        ArchiveFormat::Raw => extract_raw(src, &dest.join(raw_file_name)),
+        #[allow(unreachable_patterns)]
+        format => Err(Error::UnsupportedArchiveFormat(format.to_string())),
+    }
+}
+
+#[cfg(any(
+    feature = "zip",
+    feature = "tar-gz",
+    feature = "tar-xz",
+    feature = "tar-zst"
+))]
+fn open(src: &Path) -> Result<fs::File, Error> {
+    fs::File::open(src).map_err(|err| Error::CommonFileError {
+        message: format!("failed to open downloaded file `{:?}`", src),
+        source: err,
+    })
+}
+
+#[cfg(feature = "zip")]
+pub fn extract_zip(src: impl AsRef<Path>, dest: impl AsRef<Path>) -> Result<(), Error> {
+    let src = src.as_ref();
+    let dest = dest.as_ref();
+    if !dest.exists() {
+        std::fs::create_dir_all(dest).map_err(|err| Error::CommonFileError {
+            message: format!("failed to create directories `{:?}`", &dest),
+            source: err,
+        })?;
+    }
+    let mut zip = zip::ZipArchive::new(open(src)?)?;
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        if let Some(relative_path) = file.enclosed_name().map(Path::to_path_buf) {
+            if crate::utils::leads_through_symlink(dest, &relative_path) {
+                log::warn!(
+                    "skipped {:?}, which leads through a symlink",
+                    &relative_path
+                );
+                continue;
+            }
+            let out_path = dest.join(&relative_path);
+            let out_dir = out_path.parent().unwrap();
+            if !out_dir.exists() {
+                std::fs::create_dir_all(out_dir).map_err(|err| Error::CommonFileError {
+                    message: format!("failed to extract directories `{:?}`", &out_dir),
+                    source: err,
+                })?;
+            }
+            let mode = file.unix_mode();
+            if mode.is_some_and(|mode| mode & 0o170000 == 0o120000) {
+                let mut target = String::new();
+                file.read_to_string(&mut target)
+                    .map_err(|err| Error::CommonFileError {
+                        message: format!("failed to extract symlink `{:?}`", &out_path),
+                        source: err,
+                    })?;
+                if !crate::utils::is_contained_link(&relative_path, Path::new(&target)) {
+                    log::warn!(
+                        "skipped symlink {:?} pointing outside of the package",
+                        &relative_path
+                    );
+                    continue;
+                }
+                crate::utils::symlink(&target, &out_path).map_err(|err| {
+                    Error::CommonFileError {
+                        message: format!("failed to extract symlink `{:?}`", &out_path),
+                        source: err,
+                    }
+                })?;
+                continue;
+            }
+            let mut out_file =
+                std::fs::File::create(&out_path).map_err(|err| Error::CommonFileError {
+                    message: format!("failed to create extracted file slot `{:?}`", &out_path),
+                    source: err,
+                })?;
+            std::io::copy(&mut file, &mut out_file).map_err(|err| Error::CommonFileError {
+                message: format!("failed to extract file `{:?}`", &out_file),
+                source: err,
+            })?;
+            if let Some(mtime) = zip_time(file.last_modified()) {
+                out_file
+                    .set_modified(mtime)
+                    .map_err(|err| Error::CommonFileError {
+                        message: format!("failed to set modification time of `{:?}`", &out_path),
+                        source: err,
+                    })?;
+            }
+            #[cfg(unix)]
+            if let Some(mode) = mode {
+                use std::os::unix::prelude::PermissionsExt;
+                fs::set_permissions(&out_path, fs::Permissions::from_mode(mode & 0o777))
+                    .map_err(Error::PermissionError)?;
+            }
+            trace!("unzipped {}", out_path.display());
+        }
+    }
+
+    Ok(())
+}
+
+// zip timestamps carry no time zone, so they are taken as utc
+#[cfg(feature = "zip")]
+fn zip_time(time: zip::DateTime) -> Option<std::time::SystemTime> {
+    // days since 1970-01-01 of a date in the proleptic gregorian calendar
+    let (month, day) = (time.month() as i64, time.day() as i64);
+    let year = time.year() as i64 - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    let secs =
+        days * 86400 + time.hour() as i64 * 3600 + time.minute() as i64 * 60 + time.second() as i64;
+    Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(u64::try_from(secs).ok()?))
+}
+
+// keeps mode bits and mtimes. symlinks have to point inside the package.
+#[cfg(any(feature = "tar-gz", feature = "tar-xz", feature = "tar-zst"))]
+fn extract_tar(src: impl Read, dest: &Path) -> Result<(), Error> {
+    let mut archive = tar::Archive::new(src);
+    let entries = archive.entries().map_err(|err| Error::CommonFileError {
+        message: "failed to read tar archive".to_string(),
+        source: err,
+    })?;
+    for entry in entries {
+        let mut entry = entry.map_err(|err| Error::CommonFileError {
+            message: "failed to read tar entry".to_string(),
+            source: err,
+        })?;
+        let path = entry.path().map(|path| path.into_owned()).ok();
+        if path
+            .as_ref()
+            .is_some_and(|path| crate::utils::leads_through_symlink(dest, path))
+        {
+            log::warn!("skipped {:?}, which leads through a symlink", path);
+            continue;
+        }
+        if entry.header().entry_type().is_symlink() {
+            let target = entry.link_name().ok().flatten();
+            let contained = match (&path, &target) {
+                (Some(path), Some(target)) => crate::utils::is_contained_link(path, target),
+                _ => false,
+            };
+            if !contained {
+                log::warn!("skipped symlink {:?} pointing outside of the package", path);
+                continue;
+            }
+        }
+        // entries escaping `dest` are skipped by `unpack_in`
+        entry
+            .unpack_in(dest)
+            .map_err(|err| Error::CommonFileError {
+                message: format!("failed to extract file `{:?}`", path),
+                source: err,
+            })?;
+        trace!("untarred {:?}", path);
+    }
+
+    Ok(())
+}
+
+// packs the files below `src` into `dest`. a raw artifact is the executable
+// `src` itself.
+#[cfg(feature = "cli")]
+pub fn create(
+    format: ArchiveFormat,
+    src: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let src = src.as_ref();
+    let dest = dest.as_ref();
+    match format {
+        #[cfg(feature = "zip")]
+        ArchiveFormat::Zip => create_zip(src, dest),
+        #[cfg(feature = "tar-gz")]
+        ArchiveFormat::TarGz => {
+            let encoder =
+                flate2::write::GzEncoder::new(create_file(dest)?, flate2::Compression::default());
+            finish(create_tar(encoder, src)?.finish(), dest)
+        }
+        #[cfg(feature = "tar-xz")]
+        ArchiveFormat::TarXz => {
+            let encoder = xz2::write::XzEncoder::new(create_file(dest)?, 6);
+            finish(create_tar(encoder, src)?.finish(), dest)
+        }
+        #[cfg(feature = "tar-zst")]
+        ArchiveFormat::TarZst => {
+            let encoder =
+                zstd::stream::write::Encoder::new(create_file(dest)?, 0).map_err(|err| {
+                    Error::CommonFileError {
+                        message: format!("failed to compress `{:?}`", dest),
+                        source: err,
+                    }
+                })?;
+            finish(create_tar(encoder, src)?.finish(), dest)
+        }
+        ArchiveFormat::Raw => {
+            fs::copy(src, dest).map_err(|err| Error::CommonFileError {
+                message: format!("failed to copy executable `{:?}`", src),
+                source: err,
+            })?;
+            Ok(())
+        }
+        #[allow(unreachable_patterns)]
+        format => Err(Error::UnsupportedArchiveFormat(format.to_string())),
+    }
+}
+
+#[cfg(all(
+    feature = "cli",
+    any(
+        feature = "zip",
+        feature = "tar-gz",
+        feature = "tar-xz",
+        feature = "tar-zst"
+    )
+))]
+fn create_file(dest: &Path) -> Result<fs::File, Error> {
+    fs::File::create(dest).map_err(|err| Error::CommonFileError {
+        message: format!("failed to create archive `{:?}`", dest),
+        source: err,
+    })
+}
+
+#[cfg(all(
+    feature = "cli",
+    any(feature = "tar-gz", feature = "tar-xz", feature = "tar-zst")
+))]
+fn finish(result: std::io::Result<fs::File>, dest: &Path) -> Result<(), Error> {
+    result.map(|_| ()).map_err(|err| Error::CommonFileError {
+        message: format!("failed to write archive `{:?}`", dest),
+        source: err,
+    })
+}
+
+// zip archives of this crate cannot hold symlinks, so the files they point to
+// are stored instead
+#[cfg(all(feature = "cli", feature = "zip"))]
+fn create_zip(src: &Path, dest: &Path) -> Result<(), Error> {
+    let mut zip = zip::ZipWriter::new(create_file(dest)?);
+    for relative_path in crate::install::collect_files(src)? {
+        let path = src.join(&relative_path);
+        if fs::read_link(&path).is_ok() {
+            log::warn!("stored the target of symlink {:?} in zip archive", &path);
+        }
+        let mut file = fs::File::open(&path).map_err(|err| Error::CommonFileError {
+            message: format!("failed to open `{:?}`", &path),
+            source: err,
+        })?;
+        let metadata = file.metadata().map_err(|err| Error::CommonFileError {
+            message: format!("failed to read metadata of `{:?}`", &path),
+            source: err,
+        })?;
+        let mut options = zip::write::FileOptions::default();
+        if let Some(mtime) = metadata.modified().ok().and_then(to_zip_time) {
+            options = options.last_modified_time(mtime);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::prelude::PermissionsExt;
+            options = options.unix_permissions(metadata.permissions().mode());
+        }
+        zip.start_file(crate::utils::to_slash(&relative_path), options)?;
+        std::io::copy(&mut file, &mut zip).map_err(|err| Error::CommonFileError {
+            message: format!("failed to pack `{:?}`", &path),
+            source: err,
+        })?;
+        trace!("zipped {}", path.display());
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+// the inverse of `zip_time`. zip timestamps only have a resolution of two seconds.
+#[cfg(all(feature = "cli", feature = "zip"))]
+fn to_zip_time(time: std::time::SystemTime) -> Option<zip::DateTime> {
+    let secs = time.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs();
+    let (year, month, day) = crate::utils::civil_date(secs);
+    let secs = secs % 86400;
+    zip::DateTime::from_date_and_time(
+        u16::try_from(year).ok()?,
+        month,
+        day,
+        (secs / 3600) as u8,
+        (secs % 3600 / 60) as u8,
+        (secs % 60) as u8,
+    )
+    .ok()
+}
+
+// keeps mode bits, mtimes and symlinks
+#[cfg(all(
+    feature = "cli",
+    any(feature = "tar-gz", feature = "tar-xz", feature = "tar-zst")
+))]
+fn create_tar<W: std::io::Write>(dest: W, src: &Path) -> Result<W, Error> {
+    let mut builder = tar::Builder::new(dest);
+    builder.follow_symlinks(false);
+    for relative_path in crate::install::collect_files(src)? {
+        let path = src.join(&relative_path);
+        builder
+            .append_path_with_name(&path, crate::utils::to_slash(&relative_path))
+            .map_err(|err| Error::CommonFileError {
+                message: format!("failed to pack `{:?}`", &path),
+                source: err,
+            })?;
+        trace!("tarred {}", path.display());
+    }
+    builder.into_inner().map_err(|err| Error::CommonFileError {
+        message: "failed to write tar archive".to_string(),
+        source: err,
+    })
+}
+
+fn extract_raw(src: &Path, dest: &Path) -> Result<(), Error> {
+    fs::copy(src, dest).map_err(|err| Error::CommonFileError {
+        message: format!("failed to copy downloaded executable to `{:?}`", dest),
+        source: err,
+    })?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::prelude::PermissionsExt;
+        fs::set_permissions(dest, fs::Permissions::from_mode(0o755))
+            .map_err(Error::PermissionError)?;
+    }
+    trace!("copied raw executable to {}", dest.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            ArchiveFormat::from_magic(b"PK\x03\x04\x14\x00"),
+            ArchiveFormat::Zip
+        );
+        assert_eq!(
+            ArchiveFormat::from_magic(&[0x1f, 0x8b, 8]),
+            ArchiveFormat::TarGz
+        );
+        assert_eq!(
+            ArchiveFormat::from_magic(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+            ArchiveFormat::TarXz
+        );
+        assert_eq!(
+            ArchiveFormat::from_magic(&[0x28, 0xb5, 0x2f, 0xfd]),
+            ArchiveFormat::TarZst
+        );
+        assert_eq!(ArchiveFormat::from_magic(b"\x7fELF"), ArchiveFormat::Raw);
+        assert_eq!(ArchiveFormat::from_magic(b""), ArchiveFormat::Raw);
+        assert_eq!(
+            serde_yaml::from_str::<ArchiveFormat>("tar.zst").unwrap(),
+            ArchiveFormat::TarZst
+        );
+    }
+
+    #[test]
+    fn test_extract_raw() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("download");
+        fs::write(&src, b"\x7fELF").unwrap();
+        let format = ArchiveFormat::detect(&src).unwrap();
+        extract(format, &src, dir.path().join("package"), "hello-1.0.0").unwrap();
+        assert_eq!(
+            fs::read(dir.path().join("package/hello-1.0.0")).unwrap(),
+            b"\x7fELF"
+        );
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn test_extract_zip() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("download");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&src).unwrap());
+        let options = zip::write::FileOptions::default()
+            .unix_permissions(0o755)
+            .last_modified_time(zip::DateTime::from_date_and_time(2021, 3, 4, 5, 6, 8).unwrap());
+        writer.start_file("bin/run.sh", options).unwrap();
+        writer.write_all(b"#!/bin/sh").unwrap();
+        writer.finish().unwrap();
+
+        let format = ArchiveFormat::detect(&src).unwrap();
+        extract(format, &src, dir.path().join("package"), "hello-1.0.0").unwrap();
+        let metadata = fs::metadata(dir.path().join("package/bin/run.sh")).unwrap();
+        assert_eq!(
+            metadata.modified().unwrap(),
+            std::time::UNIX_EPOCH + std::time::Duration::from_secs(1614834368)
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::prelude::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        }
+    }
+
+    // `a/l1` points to the package, so `a/l1/l2` would point to its parent
+    #[cfg(all(feature = "zip", unix))]
+    #[test]
+    fn test_extract_zip_chained_links() {
+        use std::io::Write;
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("download");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&src).unwrap());
+        // the writer cannot store symlinks, they are marked by their mode below
+        let link = zip::write::FileOptions::default().unix_permissions(0o777);
+        for (name, target) in [("a/l1", ".."), ("a/l1/l2", "..")] {
+            writer.start_file(name, link).unwrap();
+            writer.write_all(target.as_bytes()).unwrap();
+        }
+        writer
+            .start_file("a/l1/l2/x", zip::write::FileOptions::default())
+            .unwrap();
+        writer.write_all(b"escaped").unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        let mut bytes = fs::read(&src).unwrap();
+        let (file_mode, link_mode) = (0o100777u32 << 16, 0o120777u32 << 16);
+        for start in 0..bytes.len() - 46 {
+            let attributes = start + 38;
+            if bytes[start..].starts_with(b"PK\x01\x02")
+                && bytes[attributes..attributes + 4] == file_mode.to_le_bytes()
+            {
+                bytes[attributes..attributes + 4].copy_from_slice(&link_mode.to_le_bytes());
+            }
+        }
+        fs::write(&src, bytes).unwrap();
+
+        let package = dir.path().join("out/package");
+        extract(ArchiveFormat::Zip, &src, &package, "hello-1.0.0").unwrap();
+        assert_eq!(
+            fs::read_link(package.join("a/l1")).unwrap(),
+            Path::new("..")
+        );
+        // followed through the links, these would be `package/l2` and `out/x`
+        assert!(fs::symlink_metadata(package.join("l2")).is_err());
+        assert!(!dir.path().join("out/x").exists());
+    }
+
+    // packs a directory with an executable script, and extracts it again
+    #[cfg(any(
+        feature = "zip",
+        feature = "tar-gz",
+        feature = "tar-xz",
+        feature = "tar-zst"
+    ))]
+    fn check_create(format: ArchiveFormat) {
+        let dir = tempfile::tempdir().unwrap();
+        let build_dir = dir.path().join("build");
+        fs::create_dir_all(build_dir.join("bin")).unwrap();
+        fs::write(build_dir.join("bin/run.sh"), b"#!/bin/sh").unwrap();
+        let mtime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1614834368);
+        crate::utils::set_mtime(build_dir.join("bin/run.sh"), mtime).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::prelude::PermissionsExt;
+            fs::set_permissions(
+                build_dir.join("bin/run.sh"),
+                fs::Permissions::from_mode(0o755),
+            )
+            .unwrap();
+        }
+
+        let archive = dir.path().join("archive");
+        create(format, &build_dir, &archive).unwrap();
+        assert_eq!(ArchiveFormat::detect(&archive).unwrap(), format);
+        extract(format, &archive, dir.path().join("package"), "hello-1.0.0").unwrap();
+        let path = dir.path().join("package/bin/run.sh");
+        assert_eq!(fs::read(&path).unwrap(), b"#!/bin/sh");
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.modified().unwrap(), mtime);
+        #[cfg(unix)]
+        {
+            use std::os::unix::prelude::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        }
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(ArchiveFormat::from_name("raw"), Some(ArchiveFormat::Raw));
+        assert_eq!(
+            ArchiveFormat::from_name("tar.xz"),
+            Some(ArchiveFormat::TarXz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_name("hello-1.0.0.tgz"),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_name("hello-1.0.0.zip"),
+            Some(ArchiveFormat::Zip)
+        );
+        assert_eq!(ArchiveFormat::from_name("hello-1.0.0"), None);
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn test_create_zip() {
+        check_create(ArchiveFormat::Zip);
+    }
+
+    #[cfg(any(feature = "tar-gz", feature = "tar-xz", feature = "tar-zst"))]
+    fn tar() -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o755);
+        header.set_mtime(1614834368);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "lib/data.txt", &b"hello"[..])
+            .unwrap();
+        for (name, target) in [("lib/link", "data.txt"), ("lib/escape", "../../etc")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, name, target).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[cfg(any(feature = "tar-gz", feature = "tar-xz", feature = "tar-zst"))]
+    fn check_tar(compressed: Vec<u8>, expected: ArchiveFormat) {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("download");
+        fs::write(&src, compressed).unwrap();
+        let format = ArchiveFormat::detect(&src).unwrap();
+        assert_eq!(format, expected);
+        extract(format, &src, dir.path().join("package"), "hello-1.0.0").unwrap();
+        let package = dir.path().join("package");
+        assert_eq!(fs::read(package.join("lib/data.txt")).unwrap(), b"hello");
+        assert_eq!(
+            fs::metadata(package.join("lib/data.txt"))
+                .unwrap()
+                .modified()
+                .unwrap(),
+            std::time::UNIX_EPOCH + std::time::Duration::from_secs(1614834368)
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::prelude::PermissionsExt;
+            let metadata = fs::metadata(package.join("lib/data.txt")).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+            assert_eq!(fs::read(package.join("lib/link")).unwrap(), b"hello");
+            assert!(fs::symlink_metadata(package.join("lib/escape")).is_err());
+        }
+    }
+
+    #[cfg(feature = "tar-gz")]
+    #[test]
+    fn test_extract_tar_gz() {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(&tar()).unwrap();
+        check_tar(encoder.finish().unwrap(), ArchiveFormat::TarGz);
+        check_create(ArchiveFormat::TarGz);
+    }
+
+    #[cfg(feature = "tar-xz")]
+    #[test]
+    fn test_extract_tar_xz() {
+        use std::io::Write;
+        let mut encoder = xz2::write::XzEncoder::new(vec![], 6);
+        encoder.write_all(&tar()).unwrap();
+        check_tar(encoder.finish().unwrap(), ArchiveFormat::TarXz);
+        check_create(ArchiveFormat::TarXz);
+    }
+
+    #[cfg(feature = "tar-zst")]
+    #[test]
+    fn test_extract_tar_zst() {
+        check_tar(
+            zstd::stream::encode_all(&tar()[..], 0).unwrap(),
+            ArchiveFormat::TarZst,
+        );
+        check_create(ArchiveFormat::TarZst);
+    }
+}
@@ -1,40 +1,698 @@
-use crate::{data, Error};
+use log::{info, warn};
+use reqwest::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    data::{self, Manifest, DEFAULT_CHANNEL},
+    policy::UpdatePolicy,
+    signature::ManifestSignature,
+    utils::TargetInformation,
+    Error, TrustedKey,
+};
 
 pub struct UpdateChecker<'a> {
     version: data::VersionTag,
     pulishing_url: &'a str,
+    trusted_keys: &'a [TrustedKey],
+    target: TargetInformation,
+    channel: &'a str,
+    channel_switch_policy: ChannelSwitchPolicy,
+    client_id: Option<&'a str>,
+    policy: Option<&'a UpdatePolicy>,
+    cached_manifest: Option<&'a CachedManifest>,
+}
+
+// a manifest as downloaded by an earlier check. the publisher is asked whether it
+// changed through its etag, instead of sending it again.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CachedManifest {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
 }
 
 pub enum CheckUpdateResult {
     UpToDate,
-    UpdateAvailable(data::PackageTag),
+    UpdateAvailable(data::PackageTag, Urgency),
+    // a newer release exists, but nothing was published for the running target
+    NoArtifactForTarget(data::PackageTag),
+    // the running version is ahead of the selected channel, which happens after
+    // switching e.g. from beta back to stable
+    DowngradeAvailable(data::PackageTag),
 }
 
+// how badly the running version needs an available update
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Urgency {
+    // a pre-release, only offered to those who opted into its channel
+    Optional,
+    Recommended,
+    // the running version is below the minimum supported version or a
+    // mandatory release, and must not keep running
+    Required,
+}
+
+// what to do when the running version is newer than the latest release on the
+// selected channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChannelSwitchPolicy {
+    // stay on the running version until the channel catches up
+    #[default]
+    Wait,
+    // install the latest release of the channel even though it is older
+    Downgrade,
+}
 
 impl<'a> UpdateChecker<'a> {
     pub fn new(current_version: data::VersionTag, publishing_url: &'a str) -> Self {
         Self {
             version: current_version,
             pulishing_url: publishing_url,
+            trusted_keys: &[],
+            target: TargetInformation::current(),
+            channel: DEFAULT_CHANNEL,
+            channel_switch_policy: ChannelSwitchPolicy::default(),
+            client_id: None,
+            policy: None,
+            cached_manifest: None,
         }
     }
 
-    pub fn get_latest_package_tag(&self)->Result<data::PackageTag,Error>{
-        let resp = reqwest::blocking::get(self.pulishing_url)?.text()?;
-        Ok(serde_yaml::from_str::<data::PackageTag>(&resp)?)
+    pub fn target(mut self, target: TargetInformation) -> Self {
+        self.target = target;
+        self
     }
 
-    pub fn get_latest_version(&self)->Result<data::VersionTag,Error>{
-        let package_tag=self.get_latest_package_tag()?;
-        Ok(package_tag.version)
+    // once any key is trusted, manifests without a valid signature are rejected
+    pub fn trusted_keys(mut self, trusted_keys: &'a [TrustedKey]) -> Self {
+        self.trusted_keys = trusted_keys;
+        self
     }
 
-    pub fn check_update(&self)->Result<CheckUpdateResult,Error>{
-        let package_tag = self.get_latest_package_tag()?;
-        if package_tag.version > self.version {
-            return Ok(CheckUpdateResult::UpdateAvailable(package_tag));
+    pub fn channel(mut self, channel: &'a str) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    pub fn channel_switch_policy(mut self, channel_switch_policy: ChannelSwitchPolicy) -> Self {
+        self.channel_switch_policy = channel_switch_policy;
+        self
+    }
+
+    // pinned, skipped and deferred releases chosen by the user
+    pub fn policy(mut self, policy: &'a UpdatePolicy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    // places this client in staged rollouts. without it, releases that are
+    // still being rolled out are never offered.
+    pub fn client_id(mut self, client_id: &'a str) -> Self {
+        self.client_id = Some(client_id);
+        self
+    }
+
+    // only used while it was downloaded from the same publishing url
+    pub fn cached_manifest(mut self, cached_manifest: &'a CachedManifest) -> Self {
+        self.cached_manifest = Some(cached_manifest);
+        self
+    }
+
+    fn cached(&self) -> Option<&'a CachedManifest> {
+        self.cached_manifest
+            .filter(|cached| cached.url == self.pulishing_url)
+    }
+
+    pub fn get_manifest(&self) -> Result<Manifest, Error> {
+        self.read_manifest(&self.fetch_manifest()?)
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn get_manifest_async(&self) -> Result<Manifest, Error> {
+        self.read_manifest(&self.fetch_manifest_async().await?)
+    }
+
+    // downloads the manifest and its signature, unless the cached manifest is
+    // still current
+    pub fn fetch_manifest(&self) -> Result<CachedManifest, Error> {
+        let mut request = reqwest::blocking::Client::new().get(self.pulishing_url);
+        if let Some(etag) = self.cached().and_then(|cached| cached.etag.as_deref()) {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        let resp = request.send()?;
+        if let Some(cached) = self
+            .cached()
+            .filter(|_| resp.status() == StatusCode::NOT_MODIFIED)
+        {
+            info!("manifest has not changed since the last check");
+            return Ok(cached.clone());
+        }
+        let resp = resp.error_for_status()?;
+        let etag = etag(resp.headers());
+        let text = resp.text()?;
+        let signature = if self.trusted_keys.is_empty() {
+            None
         } else {
+            let resp =
+                reqwest::blocking::get(ManifestSignature::signature_url(self.pulishing_url))?;
+            if resp.status().is_success() {
+                Some(resp.text()?)
+            } else {
+                None
+            }
+        };
+        Ok(CachedManifest {
+            url: self.pulishing_url.to_string(),
+            etag,
+            text,
+            signature,
+        })
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn fetch_manifest_async(&self) -> Result<CachedManifest, Error> {
+        let mut request = reqwest::Client::new().get(self.pulishing_url);
+        if let Some(etag) = self.cached().and_then(|cached| cached.etag.as_deref()) {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        let resp = request.send().await?;
+        if let Some(cached) = self
+            .cached()
+            .filter(|_| resp.status() == StatusCode::NOT_MODIFIED)
+        {
+            info!("manifest has not changed since the last check");
+            return Ok(cached.clone());
+        }
+        let resp = resp.error_for_status()?;
+        let etag = etag(resp.headers());
+        let text = resp.text().await?;
+        let signature = if self.trusted_keys.is_empty() {
+            None
+        } else {
+            let resp = reqwest::get(ManifestSignature::signature_url(self.pulishing_url)).await?;
+            if resp.status().is_success() {
+                Some(resp.text().await?)
+            } else {
+                None
+            }
+        };
+        Ok(CachedManifest {
+            url: self.pulishing_url.to_string(),
+            etag,
+            text,
+            signature,
+        })
+    }
+
+    // checks the signature of a fetched manifest and parses it
+    pub fn read_manifest(&self, fetched: &CachedManifest) -> Result<Manifest, Error> {
+        self.parse_manifest(&fetched.text, fetched.signature.as_deref())
+    }
+
+    pub fn get_latest_package_tag(&self) -> Result<data::PackageTag, Error> {
+        let manifest = self.get_manifest()?;
+        Ok(manifest.channel(self.channel)?.clone())
+    }
+
+    // every release published on the selected channel, newest first
+    pub fn list_releases(&self) -> Result<Vec<data::PackageTag>, Error> {
+        let manifest = self.get_manifest()?;
+        Ok(manifest.releases(self.channel)?.to_vec())
+    }
+
+    // a release other than the latest, e.g. to install an intermediate version
+    pub fn get_release(&self, version: &data::VersionTag) -> Result<data::PackageTag, Error> {
+        let manifest = self.get_manifest()?;
+        Ok(manifest.release(self.channel, version)?.clone())
+    }
+
+    // the release notes of everything between the running version and `target`
+    pub fn release_notes(&self, target: &data::VersionTag) -> Result<String, Error> {
+        let manifest = self.get_manifest()?;
+        Ok(format_release_notes(
+            &self.releases_between(&manifest, target)?,
+        ))
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn release_notes_async(&self, target: &data::VersionTag) -> Result<String, Error> {
+        let manifest = self.get_manifest_async().await?;
+        Ok(format_release_notes(
+            &self.releases_between(&manifest, target)?,
+        ))
+    }
+
+    // the releases after the running version up to and including `target`,
+    // newest first
+    pub fn releases_between(
+        &self,
+        manifest: &Manifest,
+        target: &data::VersionTag,
+    ) -> Result<Vec<data::PackageTag>, Error> {
+        Ok(manifest
+            .releases(self.channel)?
+            .iter()
+            .filter(|package_tag| {
+                package_tag.version > self.version && package_tag.version <= *target
+            })
+            .cloned()
+            .collect())
+    }
+
+    // once any key is trusted, the manifest is only parsed after its signature
+    // has been verified
+    fn parse_manifest(&self, manifest: &str, signature: Option<&str>) -> Result<Manifest, Error> {
+        if !self.trusted_keys.is_empty() {
+            let signature = signature.ok_or(Error::MissingSignature)?;
+            let signature = serde_yaml::from_str::<ManifestSignature>(signature)?;
+            signature.verify(self.trusted_keys, manifest.as_bytes())?;
+            info!("verified manifest signed by `{}`", signature.key_id);
+        }
+        Manifest::from_yaml(manifest)
+    }
+
+    pub fn get_latest_version(&self) -> Result<data::VersionTag, Error> {
+        let package_tag = self.get_latest_package_tag()?;
+        Ok(package_tag.version)
+    }
+
+    pub fn check_update(&self) -> Result<CheckUpdateResult, Error> {
+        let manifest = self.get_manifest()?;
+        self.evaluate(&manifest)
+    }
+
+    #[cfg(feature = "async")]
+    pub async fn check_update_async(&self) -> Result<CheckUpdateResult, Error> {
+        let manifest = self.get_manifest_async().await?;
+        self.evaluate(&manifest)
+    }
+
+    // the version every client of the selected channel has to run at least, set
+    // by the manifest or its latest mandatory release
+    pub fn minimum_version(&self, manifest: &Manifest) -> Option<data::VersionTag> {
+        let mandatory = manifest
+            .releases(self.channel)
+            .unwrap_or_default()
+            .iter()
+            .filter(|package_tag| package_tag.mandatory && self.is_rolled_out(package_tag))
+            .map(|package_tag| &package_tag.version)
+            .max();
+        manifest
+            .minimum_supported_version
+            .as_ref()
+            .into_iter()
+            .chain(mandatory)
+            .max()
+            .cloned()
+    }
+
+    pub fn is_supported(&self, manifest: &Manifest) -> bool {
+        self.minimum_version(manifest)
+            .is_none_or(|minimum| self.version >= minimum)
+    }
+
+    // whether this client is among those offered `package_tag`. each client
+    // falls into a fixed bucket per release, so widening a rollout keeps
+    // everyone who already got it.
+    pub fn is_rolled_out(&self, package_tag: &data::PackageTag) -> bool {
+        let percentage = match package_tag.rollout {
+            Some(percentage) if percentage < 100 => percentage,
+            _ => return true,
+        };
+        match self.client_id {
+            Some(client_id) => {
+                rollout_bucket(client_id, &package_tag.version) < percentage as u64 * 100
+            }
+            None => false,
+        }
+    }
+
+    // the patches leading from the running version to `package_tag` on this
+    // target. `Reactor::update` prefers them over downloading the full artifact.
+    pub fn patch_chain(&self, package_tag: &data::PackageTag) -> Option<Vec<data::Patch>> {
+        package_tag
+            .artifact_for(&self.target)?
+            .patch_chain(&self.version, &package_tag.version)
+    }
+
+    pub fn evaluate(&self, manifest: &Manifest) -> Result<CheckUpdateResult, Error> {
+        let releases = manifest.releases(self.channel)?;
+        // releases required to keep running ignore pins and deferrals, but a
+        // skipped release, e.g. one that failed its health check, stays skipped
+        let skipped = |version: &data::VersionTag| {
+            self.policy.is_some_and(|policy| policy.is_skipped(version))
+        };
+        let policy = self.policy.filter(|_| self.is_supported(manifest));
+        if policy.is_some_and(UpdatePolicy::is_deferred) {
+            info!("updates are deferred");
             return Ok(CheckUpdateResult::UpToDate);
         }
+        // clients left out of a rollout, or pinned to older versions, are
+        // offered the newest release they may install
+        let package_tag = match releases.iter().find(|package_tag| {
+            self.is_rolled_out(package_tag)
+                && !skipped(&package_tag.version)
+                && policy.is_none_or(|policy| policy.allows(&package_tag.version))
+        }) {
+            Some(package_tag) => package_tag.clone(),
+            None => {
+                info!(
+                    "no release on channel `{}` is available to this client",
+                    self.channel
+                );
+                return Ok(CheckUpdateResult::UpToDate);
+            }
+        };
+        if package_tag.version == self.version {
+            return Ok(CheckUpdateResult::UpToDate);
+        }
+        let pinned_elsewhere = policy
+            .and_then(|policy| policy.pin.as_ref())
+            .is_some_and(|pin| !pin.matches(&self.version));
+        if package_tag.version < self.version
+            && self.channel_switch_policy == ChannelSwitchPolicy::Wait
+            && !pinned_elsewhere
+        {
+            info!(
+                "running {} is ahead of channel `{}`, waiting for it to catch up",
+                self.version, self.channel
+            );
+            return Ok(CheckUpdateResult::UpToDate);
+        }
+        if package_tag.artifact_for(&self.target).is_none() {
+            warn!(
+                "release {} has no artifact for target `{}`",
+                package_tag.version, self.target
+            );
+            return Ok(CheckUpdateResult::NoArtifactForTarget(package_tag));
+        }
+        if package_tag.version < self.version {
+            return Ok(CheckUpdateResult::DowngradeAvailable(package_tag));
+        }
+        let urgency = if !self.is_supported(manifest) {
+            Urgency::Required
+        } else if package_tag.version.is_prerelease() {
+            Urgency::Optional
+        } else {
+            Urgency::Recommended
+        };
+        Ok(CheckUpdateResult::UpdateAvailable(package_tag, urgency))
+    }
+}
+
+fn etag(headers: &header::HeaderMap) -> Option<String> {
+    headers
+        .get(header::ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(String::from)
+}
+
+// a number below 10000 derived from the client id and the release
+fn rollout_bucket(client_id: &str, version: &data::VersionTag) -> u64 {
+    let digest = Sha256::new()
+        .chain_update(client_id)
+        .chain_update(b":")
+        .chain_update(version.to_string())
+        .finalize();
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(bytes) % 10000
+}
+
+// one paragraph per release, headed by its version and date
+pub fn format_release_notes(releases: &[data::PackageTag]) -> String {
+    releases
+        .iter()
+        .map(|package_tag| {
+            let mut notes = package_tag.version.to_string();
+            if let Some(date) = &package_tag.date {
+                notes.push_str(&format!(" ({})", date));
+            }
+            if let Some(changelog) = &package_tag.changelog {
+                notes.push('\n');
+                notes.push_str(changelog.trim_end());
+            }
+            notes
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::VersionTag;
+
+    fn manifest() -> Manifest {
+        Manifest::from_yaml(
+            "channels:
+  stable:
+    version: 1.2.0
+    hash: hash
+    downloadUrl: stable
+  beta:
+    version: 1.3.0-beta.1
+    hash: hash
+    downloadUrl: beta",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_channels() {
+        let manifest = manifest();
+        let checker = UpdateChecker::new("1.2.0".parse().unwrap(), "");
+        assert!(matches!(
+            checker.evaluate(&manifest).unwrap(),
+            CheckUpdateResult::UpToDate
+        ));
+        let checker = UpdateChecker::new("1.2.0".parse().unwrap(), "").channel("beta");
+        assert!(matches!(
+            checker.evaluate(&manifest).unwrap(),
+            CheckUpdateResult::UpdateAvailable(_, Urgency::Optional)
+        ));
+        let checker = UpdateChecker::new("1.2.0".parse().unwrap(), "").channel("nightly");
+        assert!(matches!(
+            checker.evaluate(&manifest),
+            Err(Error::UnknownChannel(_))
+        ));
+    }
+
+    #[test]
+    fn test_switch_channel() {
+        let manifest = manifest();
+        let checker = UpdateChecker::new("1.3.0-beta.1".parse().unwrap(), "");
+        assert!(matches!(
+            checker.evaluate(&manifest).unwrap(),
+            CheckUpdateResult::UpToDate
+        ));
+        let checker = UpdateChecker::new("1.3.0-beta.1".parse().unwrap(), "")
+            .channel_switch_policy(ChannelSwitchPolicy::Downgrade);
+        match checker.evaluate(&manifest).unwrap() {
+            CheckUpdateResult::DowngradeAvailable(package_tag) => {
+                assert_eq!(package_tag.version, "1.2.0".parse().unwrap())
+            }
+            _ => panic!("expected downgrade"),
+        }
+    }
+
+    #[test]
+    fn test_release_notes() {
+        let manifest = Manifest::from_yaml(
+            "channels:
+  stable:
+    - version: 1.1.0
+      date: 2022-03-01
+      changelog: |
+        adds hello
+      hash: hash
+      downloadUrl: 1.1.0
+    - version: 1.3.0
+      date: 2022-05-01
+      changelog: fixes hello
+      hash: hash
+      downloadUrl: 1.3.0
+    - version: 1.2.0
+      hash: hash
+      downloadUrl: 1.2.0",
+        )
+        .unwrap();
+        let checker = UpdateChecker::new("1.1.0".parse().unwrap(), "");
+        match checker.evaluate(&manifest).unwrap() {
+            CheckUpdateResult::UpdateAvailable(package_tag, _) => {
+                assert_eq!(package_tag.version, "1.3.0".parse().unwrap())
+            }
+            _ => panic!("expected update"),
+        }
+        let releases = checker
+            .releases_between(&manifest, &"1.3.0".parse().unwrap())
+            .unwrap();
+        assert_eq!(
+            format_release_notes(&releases),
+            "1.3.0 (2022-05-01)\nfixes hello\n\n1.2.0"
+        );
+        let releases = checker
+            .releases_between(&manifest, &"1.2.0".parse().unwrap())
+            .unwrap();
+        assert_eq!(releases.len(), 1);
+        assert_eq!(releases[0].download_url.as_deref(), Some("1.2.0"));
+    }
+
+    #[test]
+    fn test_urgency() {
+        let manifest = Manifest::from_yaml(
+            "minimumSupportedVersion: 1.1.0
+channels:
+  stable:
+    - {version: 1.0.0, hash: hash, downloadUrl: 1.0.0}
+    - {version: 1.1.0, hash: hash, downloadUrl: 1.1.0}
+    - {version: 1.2.0, hash: hash, downloadUrl: 1.2.0}
+  beta:
+    - {version: 1.3.0-beta.1, hash: hash, downloadUrl: beta}
+    - {version: 1.2.0, hash: hash, downloadUrl: 1.2.0, mandatory: true}",
+        )
+        .unwrap();
+        let urgency = |version: &str, channel: &str| {
+            let checker = UpdateChecker::new(version.parse().unwrap(), "").channel(channel);
+            match checker.evaluate(&manifest).unwrap() {
+                CheckUpdateResult::UpdateAvailable(_, urgency) => Some(urgency),
+                _ => None,
+            }
+        };
+        assert_eq!(urgency("1.0.0", "stable"), Some(Urgency::Required));
+        assert_eq!(urgency("1.1.0", "stable"), Some(Urgency::Recommended));
+        assert_eq!(urgency("1.2.0", "stable"), None);
+        assert_eq!(urgency("1.1.0", "beta"), Some(Urgency::Required));
+        assert_eq!(urgency("1.2.0", "beta"), Some(Urgency::Optional));
+
+        let checker = UpdateChecker::new("1.1.0".parse().unwrap(), "");
+        assert_eq!(
+            checker.minimum_version(&manifest),
+            Some(VersionTag::new(1, 1, 0))
+        );
+        assert!(checker.is_supported(&manifest));
+        let checker = checker.channel("beta");
+        assert_eq!(
+            checker.minimum_version(&manifest),
+            Some(VersionTag::new(1, 2, 0))
+        );
+        assert!(!checker.is_supported(&manifest));
+    }
+
+    #[test]
+    fn test_rollout() {
+        let manifest = Manifest::from_yaml(
+            "channels:
+  stable:
+    - {version: 1.1.0, hash: hash, downloadUrl: 1.1.0}
+    - {version: 1.2.0, hash: hash, downloadUrl: 1.2.0, rollout: 20}",
+        )
+        .unwrap();
+        let client_ids = (0..1000)
+            .map(|i| format!("client-{}", i))
+            .collect::<Vec<_>>();
+        let offered = |manifest: &Manifest| {
+            client_ids
+                .iter()
+                .filter(|client_id| {
+                    let checker =
+                        UpdateChecker::new("1.0.0".parse().unwrap(), "").client_id(client_id);
+                    match checker.evaluate(manifest).unwrap() {
+                        CheckUpdateResult::UpdateAvailable(package_tag, _) => {
+                            package_tag.version == VersionTag::new(1, 2, 0)
+                        }
+                        _ => panic!("expected update"),
+                    }
+                })
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let first_stage = offered(&manifest);
+        assert!(
+            (150..250).contains(&first_stage.len()),
+            "{}",
+            first_stage.len()
+        );
+        assert_eq!(offered(&manifest), first_stage);
+
+        let mut widened = manifest.clone();
+        widened.channels.get_mut("stable").unwrap()[0].rollout = Some(60);
+        let second_stage = offered(&widened);
+        assert!(
+            (500..700).contains(&second_stage.len()),
+            "{}",
+            second_stage.len()
+        );
+        assert!(first_stage
+            .iter()
+            .all(|client_id| second_stage.contains(client_id)));
+
+        // without a client id, only fully rolled out releases are offered
+        let checker = UpdateChecker::new("1.0.0".parse().unwrap(), "");
+        match checker.evaluate(&manifest).unwrap() {
+            CheckUpdateResult::UpdateAvailable(package_tag, _) => {
+                assert_eq!(package_tag.version, VersionTag::new(1, 1, 0))
+            }
+            _ => panic!("expected update"),
+        }
+    }
+
+    #[test]
+    fn test_policy() {
+        let manifest = Manifest::from_yaml(
+            "minimumSupportedVersion: 1.0.0
+channels:
+  stable:
+    - {version: 1.4.2, hash: hash, downloadUrl: 1.4.2}
+    - {version: 1.4.3, hash: hash, downloadUrl: 1.4.3}
+    - {version: 1.5.0, hash: hash, downloadUrl: 1.5.0}",
+        )
+        .unwrap();
+        let evaluate = |version: &str, policy: &UpdatePolicy| {
+            let checker = UpdateChecker::new(version.parse().unwrap(), "").policy(policy);
+            match checker.evaluate(&manifest).unwrap() {
+                CheckUpdateResult::UpToDate => "up to date".to_string(),
+                CheckUpdateResult::UpdateAvailable(package_tag, _) => {
+                    format!("update {}", package_tag.version)
+                }
+                CheckUpdateResult::DowngradeAvailable(package_tag) => {
+                    format!("downgrade {}", package_tag.version)
+                }
+                CheckUpdateResult::NoArtifactForTarget(_) => panic!("unexpected result"),
+            }
+        };
+
+        let mut policy = UpdatePolicy::default();
+        assert_eq!(evaluate("1.4.2", &policy), "update 1.5.0");
+        policy.skip(VersionTag::new(1, 5, 0));
+        assert_eq!(evaluate("1.4.2", &policy), "update 1.4.3");
+        policy.pin = Some("=1.4.2".parse().unwrap());
+        assert_eq!(evaluate("1.4.2", &policy), "up to date");
+        // pinning an older version goes back to it
+        assert_eq!(evaluate("1.5.0", &policy), "downgrade 1.4.2");
+
+        let mut policy = UpdatePolicy::default();
+        policy.defer_for(std::time::Duration::from_secs(3600));
+        assert_eq!(evaluate("1.4.2", &policy), "up to date");
+        // unless the running version has to be updated anyway
+        assert_eq!(evaluate("0.9.0", &policy), "update 1.5.0");
+    }
+
+    #[test]
+    fn test_skipped_versions() {
+        let manifest = manifest();
+        let mut policy = UpdatePolicy::default();
+        policy.skip("1.3.0-beta.1".parse().unwrap());
+        let checker = UpdateChecker::new("1.2.0".parse().unwrap(), "")
+            .channel("beta")
+            .policy(&policy);
+        assert!(matches!(
+            checker.evaluate(&manifest).unwrap(),
+            CheckUpdateResult::UpToDate
+        ));
     }
 }
@@ -1,79 +1,355 @@
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, VecDeque},
+    fmt::Display,
+    str::FromStr,
+};
 
 use serde::{Deserialize, Serialize};
 
-use crate::Error;
+use crate::{archive::ArchiveFormat, utils::TargetInformation, Error};
 
-#[derive(Serialize, Deserialize, Debug)]
+pub const DEFAULT_CHANNEL: &str = "stable";
+
+// the document served at the publishing url. it maps channel names to the
+// releases published on that channel, newest first.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Manifest {
+    #[serde(deserialize_with = "deserialize_channels")]
+    pub channels: BTreeMap<String, Vec<PackageTag>>,
+    // older versions have to update before they can run
+    #[serde(
+        rename = "minimumSupportedVersion",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub minimum_supported_version: Option<VersionTag>,
+}
+
+// a bare package tag is still accepted and published as the default channel
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ManifestRepr {
+    Channels(Manifest),
+    Single(PackageTag),
+}
+
+// channels used to publish only their latest release
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ChannelRepr {
+    Releases(Vec<PackageTag>),
+    Latest(Box<PackageTag>),
+}
+
+fn deserialize_channels<'de, D>(
+    deserializer: D,
+) -> Result<BTreeMap<String, Vec<PackageTag>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let channels = BTreeMap::<String, ChannelRepr>::deserialize(deserializer)?;
+    Ok(channels
+        .into_iter()
+        .map(|(channel, releases)| {
+            let releases = match releases {
+                ChannelRepr::Releases(releases) => releases,
+                ChannelRepr::Latest(package_tag) => vec![*package_tag],
+            };
+            (channel, releases)
+        })
+        .collect())
+}
+
+impl Manifest {
+    pub fn from_yaml(text: &str) -> Result<Self, Error> {
+        let mut manifest = match serde_yaml::from_str::<ManifestRepr>(text)? {
+            ManifestRepr::Channels(manifest) => manifest,
+            ManifestRepr::Single(package_tag) => Manifest {
+                channels: BTreeMap::from([(DEFAULT_CHANNEL.to_string(), vec![package_tag])]),
+                minimum_supported_version: None,
+            },
+        };
+        for releases in manifest.channels.values_mut() {
+            releases.sort_by(|a, b| b.version.cmp(&a.version));
+        }
+        Ok(manifest)
+    }
+
+    pub fn to_yaml(&self) -> Result<String, Error> {
+        Ok(serde_yaml::to_string(self)?)
+    }
+
+    // the latest release of `channel`
+    pub fn channel(&self, channel: &str) -> Result<&PackageTag, Error> {
+        self.releases(channel)?
+            .first()
+            .ok_or_else(|| Error::UnknownChannel(channel.to_string()))
+    }
+
+    // every release of `channel`, newest first
+    pub fn releases(&self, channel: &str) -> Result<&[PackageTag], Error> {
+        self.channels
+            .get(channel)
+            .map(Vec::as_slice)
+            .ok_or_else(|| Error::UnknownChannel(channel.to_string()))
+    }
+
+    pub fn release(&self, channel: &str, version: &VersionTag) -> Result<&PackageTag, Error> {
+        self.releases(channel)?
+            .iter()
+            .find(|package_tag| &package_tag.version == version)
+            .ok_or_else(|| Error::UnknownRelease {
+                channel: channel.to_string(),
+                version: version.to_string(),
+            })
+    }
+}
+
+// a release is either published as a single package through `hash` and
+// `downloadUrl`, which is installed on every target, or as a list of
+// per-target `artifacts`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PackageTag {
     pub version: VersionTag,
-    pub hash: String,
-    #[serde(rename = "downloadUrl")]
-    pub download_url: String,
+    // when the release was published, e.g. `2022-05-01`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    // release notes, shown before updating
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changelog: Option<String>,
+    // older versions have to update before they can run, e.g. after a protocol
+    // change
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub mandatory: bool,
+    // the percentage of clients offered this release while it is rolled out in
+    // stages. every client gets it when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollout: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    #[serde(
+        rename = "downloadUrl",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub download_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<ArchiveFormat>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<Patch>,
+    // the files in the extracted package and their digests, see
+    // `files::digest`. computed from the extracted package when omitted.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub artifacts: Vec<Artifact>,
 }
 
 impl PackageTag {
     pub fn new(version: VersionTag, hash: String, download_url: String) -> Self {
         PackageTag {
             version,
-            hash,
-            download_url,
+            date: None,
+            changelog: None,
+            mandatory: false,
+            rollout: None,
+            hash: Some(hash),
+            download_url: Some(download_url),
+            format: None,
+            patches: vec![],
+            files: BTreeMap::new(),
+            artifacts: vec![],
+        }
+    }
+
+    pub fn with_artifacts(version: VersionTag, artifacts: Vec<Artifact>) -> Self {
+        PackageTag {
+            version,
+            date: None,
+            changelog: None,
+            mandatory: false,
+            rollout: None,
+            hash: None,
+            download_url: None,
+            format: None,
+            patches: vec![],
+            files: BTreeMap::new(),
+            artifacts,
+        }
+    }
+
+    pub fn artifact_for(&self, target: &TargetInformation) -> Option<Artifact> {
+        let artifact = self
+            .artifacts
+            .iter()
+            .filter(|artifact| artifact.matches(target))
+            .max_by_key(|artifact| artifact.specificity());
+        if let Some(artifact) = artifact {
+            return Some(artifact.clone());
+        }
+        match (&self.hash, &self.download_url) {
+            (Some(hash), Some(download_url)) => Some(Artifact {
+                target: None,
+                arch: None,
+                os: None,
+                hash: hash.clone(),
+                download_url: download_url.clone(),
+                format: self.format,
+                patches: self.patches.clone(),
+                files: self.files.clone(),
+            }),
+            _ => None,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+// an artifact is selected either by a rust target triple or by `os`/`arch`.
+// omitted fields match any target.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Artifact {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    pub hash: String,
+    #[serde(rename = "downloadUrl")]
+    pub download_url: String,
+    // detected from the download when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<ArchiveFormat>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<Patch>,
+    // the files in the extracted package and their digests, see
+    // `files::digest`. computed from the extracted package when omitted.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub files: BTreeMap<String, String>,
+}
+
+// a binary patch that turns the artifact of version `from` into the one of
+// version `to`. see `delta::apply` for the format.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    pub from: VersionTag,
+    pub to: VersionTag,
+    pub hash: String,
+    #[serde(rename = "downloadUrl")]
+    pub download_url: String,
+}
+
+impl Artifact {
+    pub fn matches(&self, target: &TargetInformation) -> bool {
+        if let Some(triple) = &self.target {
+            return TargetInformation::from_triple(triple).as_ref() == Some(target);
+        }
+        target.matches(self.arch.as_deref(), self.os.as_deref())
+    }
+
+    // the shortest chain of patches leading from `from` to `to`
+    pub fn patch_chain(&self, from: &VersionTag, to: &VersionTag) -> Option<Vec<Patch>> {
+        let mut chains = VecDeque::from([(from, vec![])]);
+        let mut visited = vec![from];
+        while let Some((version, chain)) = chains.pop_front() {
+            if version == to {
+                return Some(chain).filter(|chain| !chain.is_empty());
+            }
+            for patch in self.patches.iter().filter(|patch| &patch.from == version) {
+                if !visited.contains(&&patch.to) {
+                    visited.push(&patch.to);
+                    let mut chain = chain.clone();
+                    chain.push(patch.clone());
+                    chains.push_back((&patch.to, chain));
+                }
+            }
+        }
+        None
+    }
+
+    fn specificity(&self) -> usize {
+        if self.target.is_some() {
+            return 2;
+        }
+        self.arch.is_some() as usize + self.os.is_some() as usize
+    }
+}
+
+// a semantic version as described by https://semver.org. build metadata is kept
+// for display, but ignored when comparing versions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "VersionRepr", into = "String")]
 pub struct VersionTag {
     major: u32,
     minor: u32,
     patch: u32,
+    pre: Vec<Prerelease>,
+    build: Vec<String>,
 }
 
-impl PartialOrd for VersionTag {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        if self.major != other.major {
-            return Some(self.major.cmp(&other.major));
-        }
-        if self.minor != other.minor {
-            return Some(self.minor.cmp(&other.minor));
-        }
-        if self.patch != other.patch {
-            return Some(self.patch.cmp(&other.patch));
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Prerelease {
+    Numeric(u64),
+    AlphaNumeric(String),
+}
+
+impl PartialOrd for Prerelease {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Prerelease {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Prerelease::Numeric(a), Prerelease::Numeric(b)) => a.cmp(b),
+            (Prerelease::Numeric(_), Prerelease::AlphaNumeric(_)) => Ordering::Less,
+            (Prerelease::AlphaNumeric(_), Prerelease::Numeric(_)) => Ordering::Greater,
+            (Prerelease::AlphaNumeric(a), Prerelease::AlphaNumeric(b)) => a.cmp(b),
         }
-        Some(Ordering::Equal)
     }
 }
 
-impl Ord for VersionTag {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap()
+impl Display for Prerelease {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Prerelease::Numeric(n) => write!(f, "{}", n),
+            Prerelease::AlphaNumeric(s) => write!(f, "{}", s),
+        }
     }
+}
 
-    fn max(self, other: Self) -> Self
-    where
-        Self: Sized,
-    {
-        std::cmp::max_by(self, other, Ord::cmp)
+impl PartialEq for VersionTag {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
     }
+}
 
-    fn min(self, other: Self) -> Self
-    where
-        Self: Sized,
-    {
-        std::cmp::min_by(self, other, Ord::cmp)
+impl Eq for VersionTag {}
+
+impl PartialOrd for VersionTag {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
+}
 
-    fn clamp(self, min: Self, max: Self) -> Self
-    where
-        Self: Sized,
-    {
-        assert!(min <= max);
-        if self < min {
-            min
-        } else if self > max {
-            max
-        } else {
-            self
+impl Ord for VersionTag {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        if self.major != other.major {
+            return self.major.cmp(&other.major);
+        }
+        if self.minor != other.minor {
+            return self.minor.cmp(&other.minor);
+        }
+        if self.patch != other.patch {
+            return self.patch.cmp(&other.patch);
+        }
+        // a pre-release has lower precedence than the release itself
+        match (self.pre.is_empty(), other.pre.is_empty()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => self.pre.cmp(&other.pre),
         }
     }
 }
@@ -84,29 +360,164 @@ impl VersionTag {
             major,
             minor,
             patch,
+            pre: vec![],
+            build: vec![],
         }
     }
 
+    pub fn major(&self) -> u32 {
+        self.major
+    }
+
+    pub fn minor(&self) -> u32 {
+        self.minor
+    }
+
+    pub fn patch(&self) -> u32 {
+        self.patch
+    }
+
+    pub fn pre(&self) -> &[Prerelease] {
+        &self.pre
+    }
+
+    pub fn build(&self) -> &[String] {
+        &self.build
+    }
+
+    pub fn is_prerelease(&self) -> bool {
+        !self.pre.is_empty()
+    }
+
     pub fn as_string(&self) -> String {
-        format!("{}.{}.{}", self.major, self.minor, self.patch)
+        self.to_string()
+    }
+}
+
+impl Display for VersionTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre.is_empty() {
+            let pre: Vec<String> = self.pre.iter().map(|p| p.to_string()).collect();
+            write!(f, "-{}", pre.join("."))?;
+        }
+        if !self.build.is_empty() {
+            write!(f, "+{}", self.build.join("."))?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_numeric(value: &str) -> Result<u64, Error> {
+    if value.is_empty()
+        || !value.bytes().all(|b| b.is_ascii_digit())
+        || (value.len() > 1 && value.starts_with('0'))
+    {
+        return Err(Error::InvalidLocalVersionError);
     }
+    value
+        .parse::<u64>()
+        .map_err(|_| Error::InvalidLocalVersionError)
+}
+
+fn is_identifier(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-')
 }
 
 impl TryFrom<&str> for VersionTag {
     type Error = Error;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        let mut parts = value.split(".");
-        if parts.clone().count() < 3 {
+        let (value, build) = match value.split_once('+') {
+            Some((value, build)) => (value, Some(build)),
+            None => (value, None),
+        };
+        let (core, pre) = match value.split_once('-') {
+            Some((core, pre)) => (core, Some(pre)),
+            None => (value, None),
+        };
+
+        let parts = core
+            .split('.')
+            .map(|part| {
+                parse_numeric(part)?
+                    .try_into()
+                    .map_err(|_| Error::InvalidLocalVersionError)
+            })
+            .collect::<Result<Vec<u32>, Error>>()?;
+        if parts.len() != 3 {
             return Err(Error::InvalidLocalVersionError);
         }
-        let major = parts.next().unwrap().parse::<u32>().map_err(|_| Error::InvalidLocalVersionError)?;
-        let minor = parts.next().unwrap().parse::<u32>().map_err(|_| Error::InvalidLocalVersionError)?;
-        let patch = parts.next().unwrap().parse::<u32>().map_err(|_| Error::InvalidLocalVersionError)?;
-        Ok(VersionTag::new(major, minor, patch))
+        let mut version = VersionTag::new(parts[0], parts[1], parts[2]);
+
+        if let Some(pre) = pre {
+            for identifier in pre.split('.') {
+                if !is_identifier(identifier) {
+                    return Err(Error::InvalidLocalVersionError);
+                }
+                if identifier.bytes().all(|b| b.is_ascii_digit()) {
+                    version
+                        .pre
+                        .push(Prerelease::Numeric(parse_numeric(identifier)?));
+                } else {
+                    version
+                        .pre
+                        .push(Prerelease::AlphaNumeric(identifier.to_string()));
+                }
+            }
+        }
+        if let Some(build) = build {
+            for identifier in build.split('.') {
+                if !is_identifier(identifier) {
+                    return Err(Error::InvalidLocalVersionError);
+                }
+                version.build.push(identifier.to_string());
+            }
+        }
+        Ok(version)
+    }
+}
+
+impl FromStr for VersionTag {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        VersionTag::try_from(s)
+    }
+}
+
+// versions used to be written as a `major`/`minor`/`patch` map, which is still
+// accepted when reading a manifest.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VersionRepr {
+    Text(String),
+    Legacy { major: u32, minor: u32, patch: u32 },
+}
+
+impl TryFrom<VersionRepr> for VersionTag {
+    type Error = Error;
+
+    fn try_from(value: VersionRepr) -> Result<Self, Self::Error> {
+        match value {
+            VersionRepr::Text(text) => VersionTag::try_from(text.as_str()),
+            VersionRepr::Legacy {
+                major,
+                minor,
+                patch,
+            } => Ok(VersionTag::new(major, minor, patch)),
+        }
     }
 }
 
+impl From<VersionTag> for String {
+    fn from(value: VersionTag) -> Self {
+        value.to_string()
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -126,9 +537,232 @@ mod tests {
         assert!(v1 < v4);
         assert!(v1 < v5);
     }
-#[test]
-    fn test_dump(){
-        let package_tag=PackageTag::new("1.2.3".try_into().unwrap(), "114514".to_string(), "1919810".to_string());
-        println!("{}",serde_yaml::to_string(&package_tag).unwrap());
+    #[test]
+    fn test_precedence() {
+        let versions = [
+            "1.0.0-alpha",
+            "1.0.0-alpha.1",
+            "1.0.0-alpha.beta",
+            "1.0.0-beta",
+            "1.0.0-beta.2",
+            "1.0.0-beta.11",
+            "1.0.0-rc.1",
+            "1.0.0",
+            "1.0.1",
+        ];
+        for pair in versions.windows(2) {
+            let lower: VersionTag = pair[0].parse().unwrap();
+            let higher: VersionTag = pair[1].parse().unwrap();
+            assert!(lower < higher, "{} < {}", lower, higher);
+        }
+        let release: VersionTag = "1.0.0+build.5".parse().unwrap();
+        assert_eq!(release, VersionTag::new(1, 0, 0));
+        assert_eq!(release.to_string(), "1.0.0+build.5");
+    }
+
+    #[test]
+    fn test_parse() {
+        let version: VersionTag = "1.2.3-beta.1+exp.sha.5114f85".parse().unwrap();
+        assert_eq!(
+            version.pre(),
+            &[
+                Prerelease::AlphaNumeric("beta".to_string()),
+                Prerelease::Numeric(1)
+            ]
+        );
+        assert_eq!(version.build(), &["exp", "sha", "5114f85"]);
+        assert_eq!(version.to_string(), "1.2.3-beta.1+exp.sha.5114f85");
+
+        for invalid in [
+            "1.2",
+            "1.2.3.4",
+            "01.2.3",
+            "1.2.3-",
+            "1.2.3-beta..1",
+            "1.2.3-beta.01",
+            "1.2.3+",
+            "1.2.3-be_ta",
+            "v1.2.3",
+            "1.2.3.exe",
+        ] {
+            assert!(VersionTag::try_from(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_serde() {
+        let version: VersionTag = serde_yaml::from_str("1.2.3-rc.1").unwrap();
+        assert_eq!(version, "1.2.3-rc.1".parse().unwrap());
+        let legacy: VersionTag = serde_yaml::from_str("major: 1\nminor: 2\npatch: 3").unwrap();
+        assert_eq!(legacy, VersionTag::new(1, 2, 3));
+        assert_eq!(
+            serde_yaml::to_string(&version).unwrap().trim_end(),
+            "---\n1.2.3-rc.1"
+        );
+        assert!(serde_yaml::from_str::<VersionTag>("1.2.3.4").is_err());
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest = Manifest::from_yaml(
+            "channels:
+  stable:
+    version: 1.2.0
+    hash: hash
+    downloadUrl: stable
+  beta:
+    version: 1.3.0-beta.1
+    hash: hash
+    downloadUrl: beta",
+        )
+        .unwrap();
+        assert_eq!(
+            manifest.channel("beta").unwrap().version,
+            "1.3.0-beta.1".parse().unwrap()
+        );
+        assert!(matches!(
+            manifest.channel("nightly"),
+            Err(Error::UnknownChannel(_))
+        ));
+
+        let history = Manifest::from_yaml(
+            "channels:
+  stable:
+    - version: 1.1.0
+      hash: hash
+      downloadUrl: 1.1.0
+    - version: 1.2.0
+      date: 2022-05-01
+      changelog: fixes
+      hash: hash
+      downloadUrl: 1.2.0",
+        )
+        .unwrap();
+        let versions = history
+            .releases("stable")
+            .unwrap()
+            .iter()
+            .map(|package_tag| package_tag.version.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(versions, ["1.2.0", "1.1.0"]);
+        assert_eq!(
+            history.channel("stable").unwrap().changelog.as_deref(),
+            Some("fixes")
+        );
+        let release = history
+            .release("stable", &VersionTag::new(1, 1, 0))
+            .unwrap();
+        assert_eq!(release.download_url.as_deref(), Some("1.1.0"));
+        assert!(matches!(
+            history.release("stable", &VersionTag::new(1, 0, 0)),
+            Err(Error::UnknownRelease { .. })
+        ));
+        let reparsed = Manifest::from_yaml(&history.to_yaml().unwrap()).unwrap();
+        assert_eq!(reparsed.releases("stable").unwrap().len(), 2);
+
+        let legacy = Manifest::from_yaml("version: 1.2.0\nhash: hash\ndownloadUrl: url").unwrap();
+        assert_eq!(
+            legacy.channel(DEFAULT_CHANNEL).unwrap().version,
+            VersionTag::new(1, 2, 0)
+        );
+    }
+
+    #[test]
+    fn test_dump() {
+        let package_tag = PackageTag::new(
+            "1.2.3".try_into().unwrap(),
+            "114514".to_string(),
+            "1919810".to_string(),
+        );
+        println!("{}", serde_yaml::to_string(&package_tag).unwrap());
+    }
+
+    #[test]
+    fn test_artifact_for() {
+        let artifact = |target: Option<&str>, arch: Option<&str>, os: Option<&str>| Artifact {
+            target: target.map(String::from),
+            arch: arch.map(String::from),
+            os: os.map(String::from),
+            hash: "hash".to_string(),
+            download_url: format!("{:?}-{:?}-{:?}", target, arch, os),
+            format: None,
+            patches: vec![],
+            files: BTreeMap::new(),
+        };
+        let package_tag = PackageTag::with_artifacts(
+            VersionTag::new(1, 2, 3),
+            vec![
+                artifact(None, None, Some("linux")),
+                artifact(None, Some("x86_64"), Some("linux")),
+                artifact(Some("x86_64-pc-windows-msvc"), None, None),
+            ],
+        );
+
+        let linux = TargetInformation::new("x86_64", "linux");
+        let arm_linux = TargetInformation::new("aarch64", "linux");
+        let windows = TargetInformation::new("x86_64", "windows");
+        let macos = TargetInformation::new("aarch64", "macos");
+        assert_eq!(
+            package_tag.artifact_for(&linux),
+            Some(artifact(None, Some("x86_64"), Some("linux")))
+        );
+        assert_eq!(
+            package_tag.artifact_for(&arm_linux),
+            Some(artifact(None, None, Some("linux")))
+        );
+        assert_eq!(
+            package_tag.artifact_for(&windows),
+            Some(artifact(Some("x86_64-pc-windows-msvc"), None, None))
+        );
+        assert_eq!(package_tag.artifact_for(&macos), None);
+
+        let legacy = PackageTag::new(
+            VersionTag::new(1, 2, 3),
+            "hash".to_string(),
+            "url".to_string(),
+        );
+        assert_eq!(legacy.artifact_for(&macos).unwrap().download_url, "url");
+    }
+
+    #[test]
+    fn test_patch_chain() {
+        let package_tag = serde_yaml::from_str::<PackageTag>(
+            "version: 1.3.0
+hash: hash
+downloadUrl: full
+format: raw
+patches:
+  - {from: 1.0.0, to: 1.1.0, hash: a, downloadUrl: a}
+  - {from: 1.1.0, to: 1.3.0, hash: b, downloadUrl: b}
+  - {from: 1.1.0, to: 1.2.0, hash: c, downloadUrl: c}
+  - {from: 1.2.0, to: 1.3.0, hash: d, downloadUrl: d}",
+        )
+        .unwrap();
+        let artifact = package_tag
+            .artifact_for(&TargetInformation::current())
+            .unwrap();
+        let chain = |from: &str| {
+            artifact
+                .patch_chain(&from.parse().unwrap(), &package_tag.version)
+                .map(|chain| {
+                    chain
+                        .into_iter()
+                        .map(|patch| patch.download_url)
+                        .collect::<Vec<_>>()
+                })
+        };
+        assert_eq!(chain("1.0.0"), Some(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(chain("1.2.0"), Some(vec!["d".to_string()]));
+        assert_eq!(chain("1.3.0"), None);
+        assert_eq!(chain("0.9.0"), None);
+
+        let zipped = Artifact {
+            format: Some(ArchiveFormat::Zip),
+            ..artifact.clone()
+        };
+        assert_eq!(
+            zipped.patch_chain(&"1.2.0".parse().unwrap(), &package_tag.version),
+            Some(vec![package_tag.patches[3].clone()])
+        );
     }
 }
@@ -0,0 +1,124 @@
+use crate::Error;
+
+// applies a patch in the uncompressed format of the `bsdiff` crate. the patch is
+// a sequence of control blocks of three 8 byte sign-magnitude integers: the
+// number of bytes to add to the old data, the number of extra bytes to copy, and
+// how far to move in the old data afterwards. the added and extra bytes follow
+// each control block.
+pub fn apply(old: &[u8], patch: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut new = Vec::with_capacity(old.len());
+    let mut old_pos: i64 = 0;
+    let mut rest = patch;
+    while !rest.is_empty() {
+        let control = take(&mut rest, 24)?;
+        let add_len = length(read_int(&control[0..8]))?;
+        let copy_len = length(read_int(&control[8..16]))?;
+        let seek = read_int(&control[16..24]);
+
+        let diff = take(&mut rest, add_len)?;
+        let start = usize::try_from(old_pos)
+            .ok()
+            .filter(|start| {
+                start
+                    .checked_add(add_len)
+                    .is_some_and(|end| end <= old.len())
+            })
+            .ok_or_else(|| Error::InvalidPatch("reads outside of the old file".to_string()))?;
+        new.extend(
+            diff.iter()
+                .zip(&old[start..start + add_len])
+                .map(|(diff, old)| diff.wrapping_add(*old)),
+        );
+        new.extend_from_slice(take(&mut rest, copy_len)?);
+        old_pos = old_pos
+            .checked_add(add_len as i64)
+            .and_then(|pos| pos.checked_add(seek))
+            .ok_or_else(|| Error::InvalidPatch("invalid seek".to_string()))?;
+    }
+    Ok(new)
+}
+
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if rest.len() < len {
+        return Err(Error::InvalidPatch("unexpected end of patch".to_string()));
+    }
+    let (head, tail) = rest.split_at(len);
+    *rest = tail;
+    Ok(head)
+}
+
+fn read_int(buf: &[u8]) -> i64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(buf);
+    let magnitude = (u64::from_le_bytes(bytes) & (i64::MAX as u64)) as i64;
+    if buf[7] & 0x80 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+fn length(value: i64) -> Result<usize, Error> {
+    usize::try_from(value).map_err(|_| Error::InvalidPatch("negative length".to_string()))
+}
+
+#[cfg(test)]
+pub fn write_int(value: i64, buf: &mut Vec<u8>) {
+    let mut bytes = value.unsigned_abs().to_le_bytes();
+    if value < 0 {
+        bytes[7] |= 0x80;
+    }
+    buf.extend_from_slice(&bytes);
+}
+
+// the simplest valid patch: add over the common prefix, copy the rest
+#[cfg(test)]
+pub fn naive_diff(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let common = old.len().min(new.len());
+    let mut patch = vec![];
+    write_int(common as i64, &mut patch);
+    write_int((new.len() - common) as i64, &mut patch);
+    write_int(0, &mut patch);
+    patch.extend(
+        new[..common]
+            .iter()
+            .zip(&old[..common])
+            .map(|(new, old)| new.wrapping_sub(*old)),
+    );
+    patch.extend_from_slice(&new[common..]);
+    patch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let (old, new) = (
+            b"hello world, v1".to_vec(),
+            b"hello world, v2 with more".to_vec(),
+        );
+        assert_eq!(apply(&old, &naive_diff(&old, &new)).unwrap(), new);
+        assert_eq!(apply(&new, &naive_diff(&new, &old)).unwrap(), old);
+
+        // copy "world" twice by seeking back in the old data
+        let mut patch = vec![];
+        for (add, seek) in [(5, 1), (5, -5), (5, 0)] {
+            write_int(add, &mut patch);
+            write_int(0, &mut patch);
+            write_int(seek, &mut patch);
+            patch.extend(std::iter::repeat_n(0, add as usize));
+        }
+        assert_eq!(apply(b"hello world", &patch).unwrap(), b"helloworldworld");
+
+        assert!(matches!(
+            apply(&old, &naive_diff(&old, &new)[..30]),
+            Err(Error::InvalidPatch(_))
+        ));
+        assert!(matches!(
+            apply(b"short", &naive_diff(&old, &new)),
+            Err(Error::InvalidPatch(_))
+        ));
+    }
+}
@@ -0,0 +1,651 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, BufRead, Read, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{trace, warn};
+use reqwest::{
+    header::{self, HeaderMap},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hash::{HashAlgorithm, Hasher, PackageHash},
+    progress::{DownloadProgress, ProgressReporter},
+    Error,
+};
+
+// how often and how patiently a download is retried after a network failure
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    // waits somewhere between half and the full backoff, so that clients which
+    // failed together do not retry in lockstep
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn no_retry() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    // delay before the attempt following the `retry`-th failed one
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self.initial_backoff.as_secs_f64()
+            * self.multiplier.max(1.0).powi(retry.min(64) as i32);
+        let backoff = backoff.min(self.max_backoff.as_secs_f64());
+        // without randomness from the os the full backoff is used
+        match self.jitter.then(random_fraction).flatten() {
+            Some(fraction) => Duration::from_secs_f64(backoff * (0.5 + fraction / 2.0)),
+            None => Duration::from_secs_f64(backoff),
+        }
+    }
+}
+
+// uniformly distributed in `[0, 1)`, from the os random number generator
+fn random_fraction() -> Option<f64> {
+    let mut bytes = [0; 8];
+    getrandom::getrandom(&mut bytes).ok()?;
+    Some((u64::from_le_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64)
+}
+
+// remembers where a partial download came from, so that it is only resumed
+// against the same, unchanged resource
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+struct PartialDownload {
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    validator: Option<String>,
+}
+
+impl PartialDownload {
+    fn path(dest: &Path) -> PathBuf {
+        let mut file_name = dest.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".meta");
+        dest.with_file_name(file_name)
+    }
+
+    fn load(dest: &Path) -> Option<Self> {
+        let text = fs::read_to_string(Self::path(dest)).ok()?;
+        serde_yaml::from_str(&text).ok()
+    }
+
+    fn save(&self, dest: &Path) -> Result<(), Error> {
+        let path = Self::path(dest);
+        fs::write(&path, serde_yaml::to_string(self)?).map_err(|err| Error::CommonFileError {
+            message: format!("failed to write download metadata `{:?}`", &path),
+            source: err,
+        })
+    }
+}
+
+// number of bytes already downloaded from `url`, and the validator to resume with
+fn resume_offset(url: &str, dest: &Path) -> (u64, Option<String>) {
+    match PartialDownload::load(dest) {
+        Some(partial) if partial.url == url => (
+            fs::metadata(dest).map(|meta| meta.len()).unwrap_or(0),
+            partial.validator,
+        ),
+        _ => (0, None),
+    }
+}
+
+// weak etags must not be used with `If-Range`
+fn validator(headers: &HeaderMap) -> Option<String> {
+    let header = |name| headers.get(name).and_then(|val| val.to_str().ok());
+    header(header::ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(header::LAST_MODIFIED))
+        .map(str::to_string)
+}
+
+// removes a downloaded file together with its resume metadata
+pub fn discard(dest: impl AsRef<Path>) -> Result<(), Error> {
+    let dest = dest.as_ref();
+    for path in [dest.to_path_buf(), PartialDownload::path(dest)] {
+        if path.exists() {
+            fs::remove_file(&path).map_err(|err| Error::CommonFileError {
+                message: format!("failed to remove downloaded file `{:?}`", &path),
+                source: err,
+            })?;
+        }
+    }
+    Ok(())
+}
+
+enum Failure {
+    // worth another attempt, e.g. a dropped connection or a 5xx
+    Transient(Error),
+    // the partial file cannot be resumed and has to be downloaded again
+    Restart(Error),
+    Fatal(Error),
+}
+
+impl From<reqwest::Error> for Failure {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_builder() {
+            Failure::Fatal(err.into())
+        } else {
+            Failure::Transient(err.into())
+        }
+    }
+}
+
+// returns the offset the response body starts at
+fn check_response(
+    status: StatusCode,
+    offset: u64,
+    content_range: Option<&str>,
+) -> Result<u64, Failure> {
+    let range_start = content_range
+        .and_then(|range| range.strip_prefix("bytes "))
+        .and_then(|range| range.split('-').next())
+        .and_then(|start| start.parse::<u64>().ok());
+    match status {
+        StatusCode::PARTIAL_CONTENT if offset > 0 && range_start == Some(offset) => Ok(offset),
+        StatusCode::PARTIAL_CONTENT | StatusCode::RANGE_NOT_SATISFIABLE => Err(Failure::Restart(
+            Error::NetError(format!("cannot resume download at byte {}", offset)),
+        )),
+        status if status.is_success() => Ok(0),
+        status
+            if status.is_server_error()
+                || status == StatusCode::REQUEST_TIMEOUT
+                || status == StatusCode::TOO_MANY_REQUESTS =>
+        {
+            Err(Failure::Transient(Error::NetError(format!(
+                "request failed with status: {:?}",
+                status
+            ))))
+        }
+        status => Err(Failure::Fatal(Error::NetError(format!(
+            "request failed with status: {:?}",
+            status
+        )))),
+    }
+}
+
+// returns how long to wait before the next attempt, or the error once retrying
+// is pointless
+fn retry_after(
+    failure: Failure,
+    dest: &Path,
+    retry: u32,
+    retry_policy: &RetryPolicy,
+) -> Result<Duration, Error> {
+    let (err, retryable) = match failure {
+        Failure::Transient(err) => (err, true),
+        Failure::Restart(err) => {
+            discard(dest)?;
+            (err, true)
+        }
+        Failure::Fatal(err) => (err, false),
+    };
+    if !retryable || retry + 1 >= retry_policy.max_attempts {
+        return Err(err);
+    }
+    let backoff = retry_policy.backoff(retry);
+    warn!(
+        "download attempt {} failed: {}, retrying in {:?}",
+        retry + 1,
+        err,
+        backoff
+    );
+    Ok(backoff)
+}
+
+fn file_error(message: &str, path: &Path) -> impl FnOnce(io::Error) -> Error {
+    let message = format!("{} `{:?}`", message, path);
+    move |err| Error::CommonFileError {
+        message,
+        source: err,
+    }
+}
+
+// turns the bytes written by one attempt into progress reports
+struct Meter {
+    start: u64,
+    total: Option<u64>,
+    started_at: Instant,
+}
+
+impl Meter {
+    fn new(start: u64, content_length: Option<u64>) -> Self {
+        Self {
+            start,
+            total: content_length.map(|len| start + len),
+            started_at: Instant::now(),
+        }
+    }
+
+    fn report(&self, downloaded: u64, reporter: &dyn ProgressReporter) {
+        let elapsed = self.started_at.elapsed().as_secs_f64();
+        let bytes_per_second = if elapsed > 0.0 {
+            (downloaded - self.start) as f64 / elapsed
+        } else {
+            0.0
+        };
+        reporter.download(DownloadProgress {
+            downloaded,
+            total: self.total,
+            bytes_per_second,
+        });
+    }
+}
+
+// opens `dest` for writing at `start`, with the hash of everything before it
+fn open_dest(
+    url: &str,
+    dest: &Path,
+    start: u64,
+    headers: &HeaderMap,
+    algorithm: HashAlgorithm,
+) -> Result<(fs::File, Hasher), Error> {
+    let mut hasher = algorithm.hasher();
+    let file = if start > 0 {
+        let mut file = fs::File::open(dest).map_err(file_error("failed to open file", dest))?;
+        let mut buf = [0; 64 * 1024];
+        loop {
+            let n = file
+                .read(&mut buf)
+                .map_err(file_error("failed to read file", dest))?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+        OpenOptions::new()
+            .append(true)
+            .open(dest)
+            .map_err(file_error("failed to open file", dest))?
+    } else {
+        fs::File::create(dest).map_err(file_error("failed to create file", dest))?
+    };
+    PartialDownload {
+        url: url.to_string(),
+        validator: validator(headers),
+    }
+    .save(dest)?;
+    Ok((file, hasher))
+}
+
+fn attempt(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    dest: &Path,
+    algorithm: HashAlgorithm,
+    reporter: &dyn ProgressReporter,
+) -> Result<PackageHash, Failure> {
+    let (offset, validator) = resume_offset(url, dest);
+    let mut request = client.get(url);
+    if offset > 0 {
+        trace!("resuming download at byte {}", offset);
+        request = request.header(header::RANGE, format!("bytes={}-", offset));
+        if let Some(validator) = validator {
+            request = request.header(header::IF_RANGE, validator);
+        }
+    }
+    let resp = request.send()?;
+    let content_range = resp
+        .headers()
+        .get(header::CONTENT_RANGE)
+        .and_then(|val| val.to_str().ok());
+    let start = check_response(resp.status(), offset, content_range)?;
+    let (mut file, mut hasher) =
+        open_dest(url, dest, start, resp.headers(), algorithm).map_err(Failure::Fatal)?;
+    let meter = Meter::new(start, resp.content_length());
+    meter.report(start, reporter);
+
+    let mut downloaded = start;
+    let mut src = io::BufReader::new(resp);
+    loop {
+        let buf = src
+            .fill_buf()
+            .map_err(|err| Failure::Transient(err.into()))?;
+        let n = buf.len();
+        if n == 0 {
+            break;
+        }
+        file.write_all(buf)
+            .map_err(|err| Failure::Fatal(file_error("failed to write file", dest)(err)))?;
+        hasher.update(buf);
+        src.consume(n);
+        downloaded += n as u64;
+        meter.report(downloaded, reporter);
+    }
+
+    Ok(hasher.finalize())
+}
+
+// downloads `url` into `dest`, hashing it on the way. an interrupted download is
+// kept next to `dest` and resumed by the next attempt, even across restarts.
+pub fn download_file(
+    url: &str,
+    dest: impl AsRef<Path>,
+    algorithm: HashAlgorithm,
+    retry_policy: &RetryPolicy,
+    reporter: &dyn ProgressReporter,
+) -> Result<PackageHash, Error> {
+    let dest = dest.as_ref();
+    trace!("start downloading file from `{}` to `{:?}`", url, dest);
+    let client = reqwest::blocking::Client::new();
+    let mut retry = 0;
+    loop {
+        match attempt(&client, url, dest, algorithm, reporter) {
+            Ok(hash) => {
+                fs::remove_file(PartialDownload::path(dest)).ok();
+                return Ok(hash);
+            }
+            Err(failure) => thread::sleep(retry_after(failure, dest, retry, retry_policy)?),
+        }
+        retry += 1;
+    }
+}
+
+#[cfg(feature = "async")]
+mod nonblocking {
+    use std::path::Path;
+
+    use log::trace;
+    use reqwest::header;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::{
+        check_response, resume_offset, retry_after, validator, Failure, Meter, PartialDownload,
+    };
+    use crate::{
+        hash::{HashAlgorithm, Hasher, PackageHash},
+        progress::ProgressReporter,
+        Error, RetryPolicy,
+    };
+
+    async fn open_dest(
+        url: &str,
+        dest: &Path,
+        start: u64,
+        headers: &header::HeaderMap,
+        algorithm: HashAlgorithm,
+    ) -> Result<(tokio::fs::File, Hasher), Error> {
+        let mut hasher = algorithm.hasher();
+        let file = if start > 0 {
+            let mut file = tokio::fs::File::open(dest)
+                .await
+                .map_err(super::file_error("failed to open file", dest))?;
+            let mut buf = vec![0; 64 * 1024];
+            loop {
+                let n = file
+                    .read(&mut buf)
+                    .await
+                    .map_err(super::file_error("failed to read file", dest))?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buf[..n]);
+            }
+            tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(dest)
+                .await
+                .map_err(super::file_error("failed to open file", dest))?
+        } else {
+            tokio::fs::File::create(dest)
+                .await
+                .map_err(super::file_error("failed to create file", dest))?
+        };
+        PartialDownload {
+            url: url.to_string(),
+            validator: validator(headers),
+        }
+        .save(dest)?;
+        Ok((file, hasher))
+    }
+
+    async fn attempt(
+        client: &reqwest::Client,
+        url: &str,
+        dest: &Path,
+        algorithm: HashAlgorithm,
+        reporter: &dyn ProgressReporter,
+    ) -> Result<PackageHash, Failure> {
+        let (offset, validator) = resume_offset(url, dest);
+        let mut request = client.get(url);
+        if offset > 0 {
+            trace!("resuming download at byte {}", offset);
+            request = request.header(header::RANGE, format!("bytes={}-", offset));
+            if let Some(validator) = validator {
+                request = request.header(header::IF_RANGE, validator);
+            }
+        }
+        let mut resp = request.send().await?;
+        let content_range = resp
+            .headers()
+            .get(header::CONTENT_RANGE)
+            .and_then(|val| val.to_str().ok());
+        let start = check_response(resp.status(), offset, content_range)?;
+        let (mut file, mut hasher) = open_dest(url, dest, start, resp.headers(), algorithm)
+            .await
+            .map_err(Failure::Fatal)?;
+        let meter = Meter::new(start, resp.content_length());
+        meter.report(start, reporter);
+
+        let mut downloaded = start;
+        while let Some(chunk) = resp.chunk().await? {
+            file.write_all(&chunk).await.map_err(|err| {
+                Failure::Fatal(super::file_error("failed to write file", dest)(err))
+            })?;
+            hasher.update(&chunk);
+            downloaded += chunk.len() as u64;
+            meter.report(downloaded, reporter);
+        }
+        file.flush()
+            .await
+            .map_err(|err| Failure::Fatal(super::file_error("failed to write file", dest)(err)))?;
+
+        Ok(hasher.finalize())
+    }
+
+    pub async fn download_file_async(
+        url: &str,
+        dest: impl AsRef<Path>,
+        algorithm: HashAlgorithm,
+        retry_policy: &RetryPolicy,
+        reporter: &dyn ProgressReporter,
+    ) -> Result<PackageHash, Error> {
+        let dest = dest.as_ref();
+        trace!("start downloading file from `{}` to `{:?}`", url, dest);
+        let client = reqwest::Client::new();
+        let mut retry = 0;
+        loop {
+            match attempt(&client, url, dest, algorithm, reporter).await {
+                Ok(hash) => {
+                    tokio::fs::remove_file(PartialDownload::path(dest))
+                        .await
+                        .ok();
+                    return Ok(hash);
+                }
+                Err(failure) => {
+                    tokio::time::sleep(retry_after(failure, dest, retry, retry_policy)?).await
+                }
+            }
+            retry += 1;
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+pub use nonblocking::download_file_async;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::SilentReporter;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            jitter: false,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+        assert_eq!(policy.backoff(100), Duration::from_secs(30));
+
+        let policy = RetryPolicy::default();
+        for retry in 0..8 {
+            let backoff = policy.backoff(retry);
+            let full = RetryPolicy {
+                jitter: false,
+                ..policy
+            }
+            .backoff(retry);
+            assert!(backoff >= full / 2 && backoff <= full);
+        }
+    }
+
+    #[test]
+    fn test_check_response() {
+        assert!(matches!(check_response(StatusCode::OK, 0, None), Ok(0)));
+        // the server ignored the range and sent everything again
+        assert!(matches!(check_response(StatusCode::OK, 10, None), Ok(0)));
+        assert!(matches!(
+            check_response(StatusCode::PARTIAL_CONTENT, 10, Some("bytes 10-99/100")),
+            Ok(10)
+        ));
+        assert!(matches!(
+            check_response(StatusCode::PARTIAL_CONTENT, 10, Some("bytes 0-99/100")),
+            Err(Failure::Restart(_))
+        ));
+        assert!(matches!(
+            check_response(StatusCode::RANGE_NOT_SATISFIABLE, 10, None),
+            Err(Failure::Restart(_))
+        ));
+        assert!(matches!(
+            check_response(StatusCode::SERVICE_UNAVAILABLE, 0, None),
+            Err(Failure::Transient(_))
+        ));
+        assert!(matches!(
+            check_response(StatusCode::NOT_FOUND, 0, None),
+            Err(Failure::Fatal(_))
+        ));
+    }
+
+    #[test]
+    fn test_resume_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("download");
+        fs::write(&dest, b"partial").unwrap();
+        assert_eq!(resume_offset("url", &dest), (0, None));
+
+        PartialDownload {
+            url: "url".to_string(),
+            validator: Some("\"etag\"".to_string()),
+        }
+        .save(&dest)
+        .unwrap();
+        assert_eq!(
+            resume_offset("url", &dest),
+            (7, Some("\"etag\"".to_string()))
+        );
+        assert_eq!(resume_offset("other", &dest), (0, None));
+
+        discard(&dest).unwrap();
+        assert!(!dest.exists() && !PartialDownload::path(&dest).exists());
+    }
+
+    fn serve(content: &[u8]) -> (tempfile::TempDir, crate::server::ReleaseServer, PackageHash) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("hello.zip"), content).unwrap();
+        let server = crate::server::ReleaseServer::bind(dir.path(), "127.0.0.1:0").unwrap();
+        let mut hasher = HashAlgorithm::Sha256.hasher();
+        hasher.update(content);
+        (dir, server, hasher.finalize())
+    }
+
+    fn quick_retries() -> RetryPolicy {
+        RetryPolicy {
+            initial_backoff: Duration::from_millis(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_download_file() {
+        let content = vec![7; 300 * 1024];
+        let (dir, server, expected_hash) = serve(&content);
+        let dest = dir.path().join("download");
+        let actual_hash = download_file(
+            &server.url("hello.zip"),
+            &dest,
+            HashAlgorithm::Sha256,
+            &RetryPolicy::no_retry(),
+            &SilentReporter,
+        )
+        .unwrap();
+        assert_eq!(actual_hash, expected_hash);
+        assert_eq!(fs::read(&dest).unwrap(), content);
+        assert!(!PartialDownload::path(&dest).exists());
+    }
+
+    #[test]
+    fn test_resume_truncated_download() {
+        let content = (0..200 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+        let (dir, server, expected_hash) = serve(&content);
+        server.fault_next(crate::server::Fault::Truncate(100 * 1024));
+        server.fault_next(crate::server::Fault::ServerError);
+        let dest = dir.path().join("download");
+        let actual_hash = download_file(
+            &server.url("hello.zip"),
+            &dest,
+            HashAlgorithm::Sha256,
+            &quick_retries(),
+            &SilentReporter,
+        )
+        .unwrap();
+        assert_eq!(actual_hash, expected_hash);
+        assert_eq!(fs::read(&dest).unwrap(), content);
+        let ranges = server
+            .requests()
+            .into_iter()
+            .map(|request| request.range)
+            .collect::<Vec<_>>();
+        assert_eq!(ranges[0], None);
+        let resumed = Some(format!("bytes={}-", 100 * 1024));
+        assert_eq!(ranges[1..], [resumed.clone(), resumed]);
+    }
+
+    #[test]
+    fn test_download_missing_file() {
+        let (dir, server, _) = serve(b"");
+        let result = download_file(
+            &server.url("missing.zip"),
+            dir.path().join("download"),
+            HashAlgorithm::Sha256,
+            &quick_retries(),
+            &SilentReporter,
+        );
+        assert!(matches!(result, Err(Error::NetError(_))));
+        // not found is not worth retrying
+        assert_eq!(server.requests().len(), 1);
+    }
+}
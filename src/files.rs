@@ -0,0 +1,413 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hash::{self, HashAlgorithm},
+    install::{self, InstallPlan},
+    utils, Error,
+};
+
+const INSTALLED_FILE: &str = "installed.yaml";
+
+// the files of a release, keyed by their `/`-separated path relative to the
+// install directory. symlinks are recorded by their target.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct FileManifest {
+    pub files: BTreeMap<String, String>,
+}
+
+impl FileManifest {
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let dir = dir.as_ref();
+        let mut files = BTreeMap::new();
+        for relative_path in install::collect_files(dir)? {
+            let digest = digest(&dir.join(&relative_path))?;
+            files.insert(utils::to_slash(&relative_path), digest);
+        }
+        Ok(Self { files })
+    }
+
+    // checks that `dir` holds exactly the files of this manifest
+    pub fn verify(&self, dir: impl AsRef<Path>) -> Result<(), Error> {
+        let actual = Self::from_dir(dir)?;
+        let paths = self.files.keys().chain(actual.files.keys());
+        for path in paths {
+            let expected = self.files.get(path);
+            let found = actual.files.get(path);
+            if expected != found {
+                return Err(Error::FileMismatch {
+                    path: path.clone(),
+                    expected: expected.map_or("no file", String::as_str).to_string(),
+                    actual: found.map_or("no file", String::as_str).to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // the manifest of the installed release, if it was installed by this crate
+    pub fn load(staging_dir: impl AsRef<Path>) -> Result<Option<Self>, Error> {
+        let path = staging_dir.as_ref().join(INSTALLED_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(&path).map_err(|err| Error::CommonFileError {
+            message: format!("failed to read installed files `{:?}`", &path),
+            source: err,
+        })?;
+        Ok(Some(serde_yaml::from_str(&text)?))
+    }
+
+    pub fn save(&self, staging_dir: impl AsRef<Path>) -> Result<(), Error> {
+        let path = staging_dir.as_ref().join(INSTALLED_FILE);
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, serde_yaml::to_string(self)?)
+            .and_then(|_| fs::rename(&temp_path, &path))
+            .map_err(|err| Error::CommonFileError {
+                message: format!("failed to write installed files `{:?}`", &path),
+                source: err,
+            })
+    }
+
+    // files of this release that are gone in `newer` and not matched by any of
+    // the `excluded` glob patterns
+    pub fn obsolete(&self, newer: &FileManifest, excluded: &[String]) -> Vec<PathBuf> {
+        self.files
+            .keys()
+            .filter(|path| !newer.files.contains_key(*path))
+            // never leave the install directory, whatever the stored manifest says
+            .filter(|path| {
+                path.split('/')
+                    .all(|component| !matches!(component, "" | "." | ".."))
+            })
+            .filter(|path| {
+                !excluded
+                    .iter()
+                    .any(|pattern| utils::glob_match(pattern, path))
+            })
+            .map(|path| path.split('/').collect())
+            .collect()
+    }
+}
+
+// how an update treats an existing file matched by a preserved glob pattern.
+// files that do not exist yet are always installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreservePolicy {
+    // keep the existing file, e.g. a user database
+    KeepExisting,
+    // keep the existing file and put the new one beside it as `<name>.new`,
+    // which is removed like any other installed file once it is obsolete
+    WriteNew,
+    // replace the file only while it still matches what the last update
+    // installed, i.e. the user has not edited it
+    OverwriteIfUnmodified,
+}
+
+fn preserve_policy(preserved: &[(String, PreservePolicy)], path: &str) -> Option<PreservePolicy> {
+    preserved
+        .iter()
+        .find(|(pattern, _)| utils::glob_match(pattern, path))
+        .map(|(_, policy)| *policy)
+}
+
+fn is_unmodified(
+    root: &Path,
+    installed_files: Option<&FileManifest>,
+    path: &str,
+) -> Result<bool, Error> {
+    match installed_files.and_then(|installed_files| installed_files.files.get(path)) {
+        Some(installed_digest) => Ok(*installed_digest == digest(&root.join(path))?),
+        None => Ok(false),
+    }
+}
+
+// decides where each file of the package goes and which installed files are
+// removed, honoring the preserved patterns. the first matching pattern wins.
+// also returns the manifest of the files the plan leaves installed.
+pub fn plan_install(
+    root: &Path,
+    package_files: &FileManifest,
+    installed_files: Option<&FileManifest>,
+    preserved: &[(String, PreservePolicy)],
+    cleanup_excluded: &[String],
+) -> Result<(InstallPlan, FileManifest), Error> {
+    let mut plan = InstallPlan::default();
+    let mut left_installed = FileManifest::default();
+    for (path, package_digest) in package_files.files.iter() {
+        let relative_path = path.split('/').collect::<PathBuf>();
+        let exists = fs::symlink_metadata(root.join(&relative_path)).is_ok();
+        let mut record = |path: &str, digest: String| {
+            left_installed.files.insert(path.to_string(), digest);
+        };
+        let dest = match preserve_policy(preserved, path) {
+            Some(policy) if exists => match policy {
+                PreservePolicy::KeepExisting => {
+                    record(path, digest(&root.join(&relative_path))?);
+                    None
+                }
+                PreservePolicy::WriteNew => {
+                    let existing_digest = digest(&root.join(&relative_path))?;
+                    let dest = if existing_digest == *package_digest {
+                        None
+                    } else {
+                        let mut file_name = relative_path.file_name().unwrap().to_os_string();
+                        file_name.push(".new");
+                        let dest = relative_path.with_file_name(file_name);
+                        record(&utils::to_slash(&dest), package_digest.clone());
+                        Some(dest)
+                    };
+                    record(path, existing_digest);
+                    dest
+                }
+                PreservePolicy::OverwriteIfUnmodified => {
+                    if is_unmodified(root, installed_files, path)? {
+                        record(path, package_digest.clone());
+                        Some(relative_path.clone())
+                    } else {
+                        // an edited file keeps the digest it was installed
+                        // with, so that it still counts as edited next time
+                        if let Some(installed_digest) = installed_files
+                            .and_then(|installed_files| installed_files.files.get(path))
+                        {
+                            record(path, installed_digest.clone());
+                        }
+                        None
+                    }
+                }
+            },
+            _ => {
+                record(path, package_digest.clone());
+                Some(relative_path.clone())
+            }
+        };
+        match dest {
+            Some(dest) => plan.files.push((relative_path, dest)),
+            None => info!("preserved existing {}", path),
+        }
+    }
+    if let Some(installed_files) = installed_files {
+        for relative_path in installed_files.obsolete(&left_installed, cleanup_excluded) {
+            let path = utils::to_slash(&relative_path);
+            let remove = match preserve_policy(preserved, &path) {
+                None => true,
+                Some(PreservePolicy::OverwriteIfUnmodified) => {
+                    root.join(&relative_path).exists()
+                        && is_unmodified(root, Some(installed_files), &path)?
+                }
+                Some(_) => false,
+            };
+            if remove {
+                plan.obsolete.push(relative_path);
+            }
+        }
+    }
+    Ok((plan, left_installed))
+}
+
+// copies the installed manifest between the staging directory and a snapshot,
+// removing the destination when there is none to copy
+pub fn copy_installed(from_dir: &Path, to_dir: &Path) -> Result<(), Error> {
+    let (from, to) = (from_dir.join(INSTALLED_FILE), to_dir.join(INSTALLED_FILE));
+    let result = if from.exists() {
+        fs::copy(&from, &to).map(|_| ())
+    } else if to.exists() {
+        fs::remove_file(&to)
+    } else {
+        Ok(())
+    };
+    result.map_err(|err| Error::CommonFileError {
+        message: format!("failed to copy installed files to `{:?}`", &to),
+        source: err,
+    })
+}
+
+pub fn digest(path: &Path) -> Result<String, Error> {
+    if let Ok(target) = fs::read_link(path) {
+        return Ok(format!("symlink:{}", utils::to_slash(&target)));
+    }
+    Ok(hash::hash_file(path, HashAlgorithm::Sha256)?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_obsolete() {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, content) in [
+            ("hello", "v1"),
+            ("plugins/a.dll", "a"),
+            ("plugins/b.dll", "b"),
+        ] {
+            let path = dir.path().join("v1").join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let v1 = FileManifest::from_dir(dir.path().join("v1")).unwrap();
+        assert_eq!(v1.files.len(), 3);
+        assert_eq!(
+            v1.files["hello"],
+            digest(&dir.path().join("v1/hello")).unwrap()
+        );
+
+        let mut v2 = v1.clone();
+        v2.files.remove("plugins/a.dll");
+        v2.files.remove("plugins/b.dll");
+        let mut tampered = v1.clone();
+        tampered
+            .files
+            .insert("../outside".to_string(), String::new());
+        assert!(!tampered
+            .obsolete(&v1, &[])
+            .contains(&PathBuf::from("../outside")));
+        assert_eq!(
+            v1.obsolete(&v2, &["b.dll".to_string()]),
+            vec![PathBuf::from("plugins").join("a.dll")]
+        );
+
+        v1.save(dir.path()).unwrap();
+        assert_eq!(FileManifest::load(dir.path()).unwrap(), Some(v1));
+        assert_eq!(FileManifest::load(dir.path().join("v1")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("plugins")).unwrap();
+        fs::write(dir.path().join("hello"), "v1").unwrap();
+        fs::write(dir.path().join("plugins/a.dll"), "a").unwrap();
+        let published = FileManifest::from_dir(dir.path()).unwrap();
+        published.verify(dir.path()).unwrap();
+
+        fs::write(dir.path().join("plugins/a.dll"), "tampered").unwrap();
+        assert!(matches!(
+            published.verify(dir.path()),
+            Err(Error::FileMismatch { path, .. }) if path == "plugins/a.dll"
+        ));
+        fs::write(dir.path().join("plugins/a.dll"), "a").unwrap();
+        fs::write(dir.path().join("extra"), "").unwrap();
+        assert!(matches!(
+            published.verify(dir.path()),
+            Err(Error::FileMismatch { path, expected, .. }) if path == "extra" && expected == "no file"
+        ));
+        fs::remove_file(dir.path().join("extra")).unwrap();
+        fs::remove_file(dir.path().join("hello")).unwrap();
+        assert!(matches!(
+            published.verify(dir.path()),
+            Err(Error::FileMismatch { path, actual, .. }) if path == "hello" && actual == "no file"
+        ));
+    }
+
+    #[test]
+    fn test_plan_install() {
+        let dir = tempfile::tempdir().unwrap();
+        let (package, root) = (dir.path().join("package"), dir.path().join("root"));
+        let write = |path: PathBuf, content: &str| {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        for file in [
+            "keep.db",
+            "new.yaml",
+            "same.yaml",
+            "clean.ini",
+            "edited.ini",
+            "fresh.ini",
+        ] {
+            write(package.join(file), "v2");
+        }
+        for file in [
+            "keep.db",
+            "new.yaml",
+            "clean.ini",
+            "edited.ini",
+            "gone.ini",
+            "gone.db",
+        ] {
+            write(root.join(file), "v1");
+        }
+        write(root.join("same.yaml"), "v2");
+        let mut installed = FileManifest::from_dir(&root).unwrap();
+        write(root.join("edited.ini"), "user");
+
+        let preserved = [
+            ("*.db".to_string(), PreservePolicy::KeepExisting),
+            ("*.yaml".to_string(), PreservePolicy::WriteNew),
+            ("*.ini".to_string(), PreservePolicy::OverwriteIfUnmodified),
+        ];
+        installed.files.remove("same.yaml");
+        let package_files = FileManifest::from_dir(&package).unwrap();
+        let (plan, left_installed) =
+            plan_install(&root, &package_files, Some(&installed), &preserved, &[]).unwrap();
+        let file = |path: &str| (PathBuf::from(path), PathBuf::from(path));
+        assert_eq!(
+            plan.files,
+            vec![
+                file("clean.ini"),
+                file("fresh.ini"),
+                (PathBuf::from("new.yaml"), PathBuf::from("new.yaml.new")),
+            ]
+        );
+        assert_eq!(plan.obsolete, vec![PathBuf::from("gone.ini")]);
+        // kept files are recorded as they are on disk, edited ones as installed
+        let v1 = digest(&root.join("gone.db")).unwrap();
+        let v2 = digest(&package.join("keep.db")).unwrap();
+        let recorded = |path: &str| left_installed.files.get(path).cloned();
+        assert_eq!(recorded("keep.db"), Some(v1.clone()));
+        assert_eq!(recorded("new.yaml"), Some(v1.clone()));
+        assert_eq!(recorded("new.yaml.new"), Some(v2.clone()));
+        assert_eq!(recorded("same.yaml"), Some(v2.clone()));
+        assert_eq!(recorded("clean.ini"), Some(v2.clone()));
+        assert_eq!(recorded("edited.ini"), Some(v1.clone()));
+        assert_eq!(recorded("fresh.ini"), Some(v2.clone()));
+        assert_eq!(left_installed.files.len(), 7);
+
+        // the next release drops `new.yaml` and may overwrite unmodified databases
+        for (from, to) in plan.files {
+            fs::copy(package.join(from), root.join(to)).unwrap();
+        }
+        fs::remove_file(root.join("gone.ini")).unwrap();
+        fs::remove_dir_all(&package).unwrap();
+        for file in [
+            "keep.db",
+            "same.yaml",
+            "clean.ini",
+            "edited.ini",
+            "fresh.ini",
+        ] {
+            write(package.join(file), "v3");
+        }
+        let preserved = [
+            ("*.db".to_string(), PreservePolicy::OverwriteIfUnmodified),
+            ("*.yaml".to_string(), PreservePolicy::WriteNew),
+            ("*.ini".to_string(), PreservePolicy::OverwriteIfUnmodified),
+        ];
+        let package_files = FileManifest::from_dir(&package).unwrap();
+        let (plan, _) = plan_install(
+            &root,
+            &package_files,
+            Some(&left_installed),
+            &preserved,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            plan.files,
+            vec![
+                file("clean.ini"),
+                file("fresh.ini"),
+                file("keep.db"),
+                (PathBuf::from("same.yaml"), PathBuf::from("same.yaml.new")),
+            ]
+        );
+        assert_eq!(plan.obsolete, vec![PathBuf::from("new.yaml.new")]);
+    }
+}
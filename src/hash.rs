@@ -0,0 +1,185 @@
+use std::{fmt::Display, fs, io::Read, path::Path};
+
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+        }
+    }
+
+    pub fn hasher(&self) -> Hasher {
+        match self {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        }
+    }
+
+    fn digest_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 => 32,
+            HashAlgorithm::Sha512 => 64,
+        }
+    }
+}
+
+impl TryFrom<&str> for HashAlgorithm {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_ascii_lowercase().as_str() {
+            "sha256" | "sha-256" => Ok(HashAlgorithm::Sha256),
+            "sha512" | "sha-512" => Ok(HashAlgorithm::Sha512),
+            _ => Err(Error::UnsupportedHashAlgorithm(value.to_string())),
+        }
+    }
+}
+
+pub enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    pub fn finalize(self) -> PackageHash {
+        match self {
+            Hasher::Sha256(hasher) => PackageHash {
+                algorithm: HashAlgorithm::Sha256,
+                digest: hex::encode(hasher.finalize()),
+            },
+            Hasher::Sha512(hasher) => PackageHash {
+                algorithm: HashAlgorithm::Sha512,
+                digest: hex::encode(hasher.finalize()),
+            },
+        }
+    }
+}
+
+// a digest written as `algo:hex`. a bare hex string is accepted as well,
+// in which case the algorithm is guessed from the digest length.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageHash {
+    algorithm: HashAlgorithm,
+    digest: String,
+}
+
+impl PackageHash {
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    pub fn verify(&self, actual: &PackageHash) -> Result<(), Error> {
+        if self != actual {
+            return Err(Error::HashMismatch {
+                expected: self.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&str> for PackageHash {
+    type Error = Error;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value = value.trim();
+        let (algorithm, digest) = match value.split_once(':') {
+            Some((algorithm, digest)) => (HashAlgorithm::try_from(algorithm)?, digest),
+            None => match value.len() {
+                64 => (HashAlgorithm::Sha256, value),
+                128 => (HashAlgorithm::Sha512, value),
+                _ => return Err(Error::InvalidHash(value.to_string())),
+            },
+        };
+        if digest.len() != algorithm.digest_len() * 2
+            || !digest.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(Error::InvalidHash(value.to_string()));
+        }
+        Ok(PackageHash {
+            algorithm,
+            digest: digest.to_ascii_lowercase(),
+        })
+    }
+}
+
+impl Display for PackageHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.algorithm.name(), self.digest)
+    }
+}
+
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> Result<PackageHash, Error> {
+    let mut file = fs::File::open(path).map_err(|err| Error::CommonFileError {
+        message: format!("failed to open `{:?}`", path),
+        source: err,
+    })?;
+    let mut hasher = algorithm.hasher();
+    let mut buf = [0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).map_err(|err| Error::CommonFileError {
+            message: format!("failed to read `{:?}`", path),
+            source: err,
+        })?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn test_parse() {
+        let prefixed = PackageHash::try_from(format!("sha256:{}", HELLO_SHA256).as_str()).unwrap();
+        let bare = PackageHash::try_from(HELLO_SHA256).unwrap();
+        assert_eq!(prefixed, bare);
+        assert_eq!(bare.algorithm(), HashAlgorithm::Sha256);
+        assert_eq!(bare.to_string(), format!("sha256:{}", HELLO_SHA256));
+
+        assert!(PackageHash::try_from("md5:d41d8cd98f00b204e9800998ecf8427e").is_err());
+        assert!(PackageHash::try_from("sha512:abcdef").is_err());
+        assert!(PackageHash::try_from("114514").is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut hasher = HashAlgorithm::Sha256.hasher();
+        hasher.update(b"hel");
+        hasher.update(b"lo");
+        let actual = hasher.finalize();
+        let expected = PackageHash::try_from(HELLO_SHA256).unwrap();
+        expected.verify(&actual).unwrap();
+
+        let mut hasher = HashAlgorithm::Sha512.hasher();
+        hasher.update(b"hello");
+        match expected.verify(&hasher.finalize()) {
+            Err(Error::HashMismatch { .. }) => {}
+            _ => panic!("expected hash mismatch"),
+        }
+    }
+}
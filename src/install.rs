@@ -0,0 +1,532 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::{info, trace, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{utils, Error};
+
+const JOURNAL_FILE: &str = "journal.yaml";
+const BACKUP_DIR: &str = "backup";
+
+// every change made to the install root is recorded here before it happens, so
+// that an interrupted install can be reverted on the next start
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Journal {
+    entries: Vec<JournalEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+enum JournalEntry {
+    CreatedDir(PathBuf),
+    Created(PathBuf),
+    // the previous content lives at the same relative path in the backup dir
+    Replaced(PathBuf),
+    Removed(PathBuf),
+}
+
+// what an install does to the install root
+#[derive(Debug, Default)]
+pub struct InstallPlan {
+    // a file of the package and where it is installed, both relative paths
+    pub files: Vec<(PathBuf, PathBuf)>,
+    pub obsolete: Vec<PathBuf>,
+}
+
+pub struct Transaction {
+    root: PathBuf,
+    staging_dir: PathBuf,
+    journal: Journal,
+}
+
+impl Transaction {
+    pub fn begin(root: impl AsRef<Path>, staging_dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let transaction = Transaction {
+            root: root.as_ref().to_path_buf(),
+            staging_dir: staging_dir.as_ref().to_path_buf(),
+            journal: Journal::default(),
+        };
+        if transaction.journal_path().exists() {
+            return Err(Error::InstallInProgress);
+        }
+        let backup_dir = transaction.backup_dir();
+        if backup_dir.exists() {
+            fs::remove_dir_all(&backup_dir).map_err(|err| Error::CommonFileError {
+                message: format!("failed to clear backup directory `{:?}`", &backup_dir),
+                source: err,
+            })?;
+        }
+        fs::create_dir_all(&backup_dir).map_err(|err| Error::CommonFileError {
+            message: format!("failed to create backup directory `{:?}`", &backup_dir),
+            source: err,
+        })?;
+        transaction.save_journal()?;
+        Ok(transaction)
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        self.staging_dir.join(JOURNAL_FILE)
+    }
+
+    fn backup_dir(&self) -> PathBuf {
+        self.staging_dir.join(BACKUP_DIR)
+    }
+
+    fn save_journal(&self) -> Result<(), Error> {
+        let path = self.journal_path();
+        let temp_path = path.with_extension("tmp");
+        let write = || -> std::io::Result<()> {
+            let mut file = fs::File::create(&temp_path)?;
+            file.write_all(
+                serde_yaml::to_string(&self.journal)
+                    .map_err(std::io::Error::other)?
+                    .as_bytes(),
+            )?;
+            file.sync_all()?;
+            fs::rename(&temp_path, &path)
+        };
+        write().map_err(|err| Error::CommonFileError {
+            message: format!("failed to write install journal `{:?}`", &path),
+            source: err,
+        })
+    }
+
+    fn record(&mut self, entry: JournalEntry) -> Result<(), Error> {
+        self.journal.entries.push(entry);
+        self.save_journal()
+    }
+
+    fn create_dir_all(&mut self, relative_dir: &Path) -> Result<(), Error> {
+        let mut missing = vec![];
+        for dir in relative_dir.ancestors() {
+            if dir.as_os_str().is_empty() || self.root.join(dir).exists() {
+                break;
+            }
+            missing.push(dir.to_path_buf());
+        }
+        for dir in missing.into_iter().rev() {
+            self.record(JournalEntry::CreatedDir(dir.clone()))?;
+            let path = self.root.join(&dir);
+            trace!(" mkdir: {:?}", &path);
+            fs::create_dir(&path).map_err(|err| Error::CommonFileError {
+                message: format!("failed to create directory `{:?}`", &path),
+                source: err,
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn install_file(
+        &mut self,
+        src: impl AsRef<Path>,
+        relative_path: &Path,
+    ) -> Result<(), Error> {
+        let src = src.as_ref();
+        if let Ok(target) = fs::read_link(src) {
+            if !utils::is_contained_link(relative_path, &target) {
+                warn!(
+                    "skipped symlink {:?} pointing outside of the install root",
+                    relative_path
+                );
+                return Ok(());
+            }
+        }
+        if let Some(parent) = relative_path.parent() {
+            self.create_dir_all(parent)?;
+        }
+        let dest = self.root.join(relative_path);
+        if fs::symlink_metadata(&dest).is_ok() {
+            self.back_up(relative_path)?;
+            self.record(JournalEntry::Replaced(relative_path.to_path_buf()))?;
+        } else {
+            self.record(JournalEntry::Created(relative_path.to_path_buf()))?;
+        }
+        trace!("  copy: {:?} -> {:?}", src, &dest);
+        utils::copy(src, &dest).map_err(|err| Error::CommonFileError {
+            message: format!("failed to install `{:?}`", &dest),
+            source: err,
+        })?;
+        Ok(())
+    }
+
+    pub fn remove_file(&mut self, relative_path: &Path) -> Result<(), Error> {
+        let path = self.root.join(relative_path);
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if !metadata.is_dir() => {}
+            _ => return Ok(()),
+        }
+        self.back_up(relative_path)?;
+        self.record(JournalEntry::Removed(relative_path.to_path_buf()))?;
+        trace!("remove: {:?}", &path);
+        fs::remove_file(&path).map_err(|err| Error::CommonFileError {
+            message: format!("failed to remove obsolete file `{:?}`", &path),
+            source: err,
+        })
+    }
+
+    fn back_up(&self, relative_path: &Path) -> Result<(), Error> {
+        let path = self.root.join(relative_path);
+        let backup = self.backup_dir().join(relative_path);
+        fs::create_dir_all(backup.parent().unwrap()).map_err(|err| Error::CommonFileError {
+            message: format!("failed to create backup directory for `{:?}`", &backup),
+            source: err,
+        })?;
+        // the backup has to be complete before the journal refers to it
+        utils::copy(&path, &backup).map_err(|err| Error::CommonFileError {
+            message: format!("failed to back up `{:?}`", &path),
+            source: err,
+        })
+    }
+
+    pub fn commit(self) -> Result<(), Error> {
+        let journal_path = self.journal_path();
+        fs::remove_file(&journal_path).map_err(|err| Error::CommonFileError {
+            message: format!("failed to remove install journal `{:?}`", &journal_path),
+            source: err,
+        })?;
+        remove_backup_dir(&self.backup_dir());
+        Ok(())
+    }
+
+    // finishes the install, but keeps the journal and the backups in
+    // `snapshot_dir` so that the install can still be reverted with `recover`
+    pub fn commit_into(self, snapshot_dir: impl AsRef<Path>) -> Result<(), Error> {
+        let snapshot_dir = snapshot_dir.as_ref();
+        let keep = || -> std::io::Result<()> {
+            if snapshot_dir.exists() {
+                fs::remove_dir_all(snapshot_dir)?;
+            }
+            fs::create_dir_all(snapshot_dir)?;
+            fs::rename(self.backup_dir(), snapshot_dir.join(BACKUP_DIR))?;
+            fs::rename(self.journal_path(), snapshot_dir.join(JOURNAL_FILE))
+        };
+        keep().map_err(|err| Error::CommonFileError {
+            message: format!("failed to keep install snapshot in `{:?}`", snapshot_dir),
+            source: err,
+        })
+    }
+
+    pub fn rollback(self) -> Result<(), Error> {
+        rollback(&self.root, &self.staging_dir, &self.journal)
+    }
+}
+
+fn remove_backup_dir(backup_dir: &Path) {
+    if let Err(err) = fs::remove_dir_all(backup_dir) {
+        warn!(
+            "failed to remove backup directory `{:?}`: {}",
+            backup_dir, err
+        );
+    }
+}
+
+fn rollback(root: &Path, staging_dir: &Path, journal: &Journal) -> Result<(), Error> {
+    let backup_dir = staging_dir.join(BACKUP_DIR);
+    for entry in journal.entries.iter().rev() {
+        match entry {
+            JournalEntry::CreatedDir(dir) => {
+                let path = root.join(dir);
+                let is_empty = fs::read_dir(&path)
+                    .map(|mut entries| entries.next().is_none())
+                    .unwrap_or(false);
+                // files created since the install are left alone
+                if is_empty {
+                    fs::remove_dir(&path).map_err(|err| Error::CommonFileError {
+                        message: format!("failed to roll back directory `{:?}`", &path),
+                        source: err,
+                    })?;
+                }
+            }
+            JournalEntry::Created(file) => {
+                let path = root.join(file);
+                if fs::symlink_metadata(&path).is_ok() {
+                    fs::remove_file(&path).map_err(|err| Error::CommonFileError {
+                        message: format!("failed to roll back file `{:?}`", &path),
+                        source: err,
+                    })?;
+                }
+            }
+            JournalEntry::Replaced(file) | JournalEntry::Removed(file) => {
+                let path = root.join(file);
+                utils::copy(backup_dir.join(file), &path).map_err(|err| {
+                    Error::CommonFileError {
+                        message: format!("failed to restore file `{:?}`", &path),
+                        source: err,
+                    }
+                })?;
+            }
+        }
+        trace!("rolled back {:?}", entry);
+    }
+    let journal_path = staging_dir.join(JOURNAL_FILE);
+    fs::remove_file(&journal_path).map_err(|err| Error::CommonFileError {
+        message: format!("failed to remove install journal `{:?}`", &journal_path),
+        source: err,
+    })?;
+    remove_backup_dir(&backup_dir);
+    Ok(())
+}
+
+// reverts an install that was interrupted, e.g. by a crash or power loss, or
+// one that was kept with `commit_into`
+pub fn recover(root: impl AsRef<Path>, staging_dir: impl AsRef<Path>) -> Result<bool, Error> {
+    let journal_path = staging_dir.as_ref().join(JOURNAL_FILE);
+    if !journal_path.exists() {
+        return Ok(false);
+    }
+    warn!("found unfinished install, rolling back");
+    let journal = fs::read_to_string(&journal_path).map_err(|err| Error::CommonFileError {
+        message: format!("failed to read install journal `{:?}`", &journal_path),
+        source: err,
+    })?;
+    let journal = serde_yaml::from_str::<Journal>(&journal)?;
+    rollback(root.as_ref(), staging_dir.as_ref(), &journal)?;
+    info!("rolled back unfinished install");
+    Ok(true)
+}
+
+pub fn collect_files(root: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut result = vec![];
+    let mut stack = vec![PathBuf::new()];
+    while let Some(relative_dir) = stack.pop() {
+        let dir = root.join(&relative_dir);
+        let entries = fs::read_dir(&dir).map_err(|err| Error::CommonFileError {
+            message: format!("failed to read directory `{:?}`", &dir),
+            source: err,
+        })?;
+        for entry in entries {
+            let entry = entry.map_err(|err| Error::CommonFileError {
+                message: format!("failed to read directory `{:?}`", &dir),
+                source: err,
+            })?;
+            let relative_path = relative_dir.join(entry.file_name());
+            // symlinks are installed as links, even when they point to a directory
+            let is_dir = entry.file_type().map(|file_type| file_type.is_dir());
+            if is_dir.map_err(|err| Error::CommonFileError {
+                message: format!("failed to read directory `{:?}`", &dir),
+                source: err,
+            })? {
+                stack.push(relative_path);
+            } else {
+                result.push(relative_path);
+            }
+        }
+    }
+    result.sort();
+    Ok(result)
+}
+
+// copies the files of `src` into `root` and removes the obsolete ones as
+// planned. either all changes are made, or the install root is restored to its
+// previous state.
+pub fn install(
+    src: impl AsRef<Path>,
+    root: impl AsRef<Path>,
+    staging_dir: impl AsRef<Path>,
+    snapshot_dir: Option<&Path>,
+    plan: &InstallPlan,
+) -> Result<(), Error> {
+    let src = src.as_ref();
+    let mut transaction = Transaction::begin(root, staging_dir)?;
+    let result = plan
+        .files
+        .iter()
+        .try_for_each(|(file, dest)| transaction.install_file(src.join(file), dest))
+        .and_then(|_| {
+            plan.obsolete
+                .iter()
+                .try_for_each(|file| transaction.remove_file(file))
+        });
+    if let Err(err) = result {
+        warn!("install failed, rolling back: {}", err);
+        transaction.rollback()?;
+        return Err(err);
+    }
+    match snapshot_dir {
+        Some(snapshot_dir) => transaction.commit_into(snapshot_dir),
+        None => transaction.commit(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(src: &Path, obsolete: &[&str]) -> InstallPlan {
+        InstallPlan {
+            files: collect_files(src)
+                .unwrap()
+                .into_iter()
+                .map(|file| (file.clone(), file))
+                .collect(),
+            obsolete: obsolete.iter().map(PathBuf::from).collect(),
+        }
+    }
+
+    fn write(path: impl AsRef<Path>, content: &str) {
+        let path = path.as_ref();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    fn read(path: impl AsRef<Path>) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_install() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, root, staging) = (
+            dir.path().join("src"),
+            dir.path().join("root"),
+            dir.path().join("staging"),
+        );
+        write(src.join("hello"), "new");
+        write(src.join("lib/plugin"), "new");
+        write(root.join("hello"), "old");
+        write(root.join("config"), "old");
+        write(root.join("lib/obsolete"), "old");
+
+        install(
+            &src,
+            &root,
+            &staging,
+            None,
+            &plan(&src, &["lib/obsolete", "missing"]),
+        )
+        .unwrap();
+        assert_eq!(read(root.join("hello")), "new");
+        assert_eq!(read(root.join("lib/plugin")), "new");
+        assert_eq!(read(root.join("config")), "old");
+        assert!(!root.join("lib/obsolete").exists());
+        assert!(!staging.join(JOURNAL_FILE).exists());
+        assert!(!staging.join(BACKUP_DIR).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_install_links_and_modes() {
+        use std::os::unix::prelude::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (src, root, staging, snapshot) = (
+            dir.path().join("src"),
+            dir.path().join("root"),
+            dir.path().join("staging"),
+            dir.path().join("snapshot"),
+        );
+        write(src.join("bin/run.sh"), "new");
+        fs::set_permissions(src.join("bin/run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+        utils::symlink("bin", src.join("scripts")).unwrap();
+        utils::symlink("../outside", src.join("escape")).unwrap();
+        write(root.join("bin/run.sh"), "old");
+        write(root.join("outside"), "data");
+        // replacing a symlink must not write through it
+        utils::symlink("outside", root.join("scripts")).unwrap();
+
+        install(&src, &root, &staging, Some(&snapshot), &plan(&src, &[])).unwrap();
+        let metadata = fs::metadata(root.join("bin/run.sh")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        assert_eq!(
+            fs::read_link(root.join("scripts")).unwrap(),
+            Path::new("bin")
+        );
+        assert_eq!(read(root.join("outside")), "data");
+        assert!(fs::symlink_metadata(root.join("escape")).is_err());
+
+        assert!(recover(&root, &snapshot).unwrap());
+        assert_eq!(
+            fs::read_link(root.join("scripts")).unwrap(),
+            Path::new("outside")
+        );
+        assert_eq!(read(root.join("bin/run.sh")), "old");
+    }
+
+    #[test]
+    fn test_rollback_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, root, staging) = (
+            dir.path().join("src"),
+            dir.path().join("root"),
+            dir.path().join("staging"),
+        );
+        write(src.join("a"), "new");
+        write(src.join("b/c"), "new");
+        write(src.join("z"), "new");
+        write(root.join("a"), "old");
+        // a directory where the package has a file makes the last copy fail
+        fs::create_dir_all(root.join("z")).unwrap();
+
+        assert!(install(&src, &root, &staging, None, &plan(&src, &[])).is_err());
+        assert_eq!(read(root.join("a")), "old");
+        assert!(!root.join("b").exists());
+        assert!(root.join("z").is_dir());
+        assert!(!staging.join(JOURNAL_FILE).exists());
+    }
+
+    #[test]
+    fn test_recover() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, root, staging) = (
+            dir.path().join("src"),
+            dir.path().join("root"),
+            dir.path().join("staging"),
+        );
+        write(src.join("a"), "new");
+        write(src.join("b"), "new");
+        write(root.join("a"), "old");
+
+        // simulate a crash in the middle of an install
+        let mut transaction = Transaction::begin(&root, &staging).unwrap();
+        transaction
+            .install_file(src.join("a"), Path::new("a"))
+            .unwrap();
+        transaction
+            .install_file(src.join("b"), Path::new("b"))
+            .unwrap();
+        drop(transaction);
+        assert_eq!(read(root.join("a")), "new");
+
+        assert!(recover(&root, &staging).unwrap());
+        assert_eq!(read(root.join("a")), "old");
+        assert!(!root.join("b").exists());
+        assert!(!recover(&root, &staging).unwrap());
+    }
+
+    #[test]
+    fn test_revert_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let (src, root, staging, snapshot) = (
+            dir.path().join("src"),
+            dir.path().join("root"),
+            dir.path().join("staging"),
+            dir.path().join("snapshot"),
+        );
+        write(src.join("a"), "new");
+        write(src.join("b/c"), "new");
+        write(root.join("a"), "old");
+        write(root.join("obsolete"), "old");
+
+        install(
+            &src,
+            &root,
+            &staging,
+            Some(&snapshot),
+            &plan(&src, &["obsolete"]),
+        )
+        .unwrap();
+        assert_eq!(read(root.join("a")), "new");
+        assert!(!root.join("obsolete").exists());
+        assert!(!recover(&root, &staging).unwrap());
+        write(root.join("b/user"), "data");
+
+        assert!(recover(&root, &snapshot).unwrap());
+        assert_eq!(read(root.join("a")), "old");
+        assert_eq!(read(root.join("obsolete")), "old");
+        assert!(!root.join("b/c").exists());
+        assert_eq!(read(root.join("b/user")), "data");
+    }
+}
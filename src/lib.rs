@@ -2,25 +2,70 @@ use std::{
     env,
     fs::{self, Permissions},
     path::{Path, PathBuf},
+    sync::Arc,
     thread,
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
-use checker::{CheckUpdateResult, UpdateChecker};
-use data::{PackageTag, VersionTag};
-use log::{info, warn};
+use checker::{ChannelSwitchPolicy, CheckUpdateResult, UpdateChecker};
+use data::Manifest;
+use files::FileManifest;
+use hash::PackageHash;
+use install::InstallPlan;
+use log::{info, trace, warn};
+use progress::{Phase, ProgressReporter};
+use restart::Restarter;
+use state::{PendingHealthCheck, UpdaterState};
 use thiserror::Error;
+use utils::TargetInformation;
 
+mod archive;
 pub mod checker;
 mod data;
+mod delta;
+mod download;
+mod files;
+mod hash;
+mod install;
+#[cfg(feature = "async")]
+mod nonblocking;
+mod policy;
+pub mod progress;
+#[cfg(feature = "cli")]
+pub mod publish;
+pub mod restart;
+#[cfg(feature = "server")]
+pub mod server;
+mod signature;
+mod state;
 mod utils;
 
+pub use archive::ArchiveFormat;
+pub use data::{PackageTag, Patch, VersionTag};
+pub use download::RetryPolicy;
+pub use files::PreservePolicy;
+pub use policy::{UpdatePolicy, VersionReq};
+pub use signature::TrustedKey;
+pub use state::InstalledVersion;
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("this os `{0}` is not supported")]
     UnsupportedOS(String),
     #[error("invalid local vesion")]
     InvalidLocalVersionError,
+    #[error("invalid version requirement `{0}`")]
+    InvalidVersionRequirement(String),
+    #[error("the name of the application is not set")]
+    MissingName,
+    #[error("the version of the application is not set")]
+    MissingVersion,
+    #[error("the publishing url is not set")]
+    MissingPublishingUrl,
+    #[error(
+        "the executable is named after version `{found}`, but the application is `{expected}`"
+    )]
+    ExecutableVersionMismatch { expected: String, found: String },
     #[error("failed to execute IO operation")]
     UntrackedFileError {
         #[from]
@@ -34,6 +79,8 @@ pub enum Error {
     },
     #[error("failed to set permission")]
     PermissionError(#[source] std::io::Error),
+    #[error("failed to get random bytes from the os")]
+    RandomnessError(#[source] getrandom::Error),
     #[error("failed to locate path of current executable")]
     SelfLocationError(#[source] std::io::Error),
     #[error("error when fetching data from the publisher")]
@@ -48,30 +95,127 @@ pub enum Error {
         #[from]
         source: serde_yaml::Error,
     },
+    #[cfg(feature = "zip")]
     #[error("failed to extract data")]
     ZipError {
         #[from]
         source: zip::result::ZipError,
     },
+    #[error("archive format `{0}` is not supported by this build")]
+    UnsupportedArchiveFormat(String),
+    #[error("unsupported hash algorithm `{0}`")]
+    UnsupportedHashAlgorithm(String),
+    #[error("invalid hash `{0}`")]
+    InvalidHash(String),
+    #[error("hash mismatch: expected `{expected}`, got `{actual}`")]
+    HashMismatch { expected: String, actual: String },
+    #[error(
+        "package file `{path}` does not match the release: expected `{expected}`, got `{actual}`"
+    )]
+    FileMismatch {
+        path: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("invalid public key `{0}`")]
+    InvalidPublicKey(String),
+    #[error("the publisher did not provide a signature for the manifest")]
+    MissingSignature,
+    #[error("invalid signing key in `{0}`")]
+    InvalidSigningKey(String),
+    #[error("manifest is signed by untrusted key `{0}`")]
+    UntrustedKey(String),
+    #[error("invalid manifest signature from key `{0}`")]
+    InvalidSignature(String),
+    #[error("invalid patch: {0}")]
+    InvalidPatch(String),
+    #[error("invalid date `{0}`, expected yyyy-mm-dd")]
+    InvalidDate(String),
+    #[error("invalid manifest: {0}")]
+    InvalidManifest(String),
+    #[error("release `{version}` has no artifact for target `{target}`")]
+    NoArtifactForTarget { version: String, target: String },
+    #[error("channel `{0}` is not published")]
+    UnknownChannel(String),
+    #[error("release `{version}` is not published on channel `{channel}`")]
+    UnknownRelease { channel: String, version: String },
+    #[error("{version} is no longer supported, at least {minimum} has to be installed")]
+    UpdateRequired {
+        version: String,
+        minimum: String,
+        #[source]
+        source: Option<Box<Error>>,
+    },
+    #[error("another install has not finished yet")]
+    InstallInProgress,
+    #[cfg(feature = "async")]
+    #[error("background task failed")]
+    TaskError(#[source] tokio::task::JoinError),
 }
 
-#[derive(Debug)]
-pub struct ReactorBuilder {
-    _name: Option<String>,
-    _version: Option<data::VersionTag>,
-    _publishing_url: Option<String>,
+// default staging directory inside the install directory. downloads, backups
+// and the install journal are kept there while an update is applied.
+const STAGING_DIR: &str = ".reduction_react";
+const DOWNLOAD_FILE: &str = "download";
+const PATCH_FILE: &str = "patch";
+const PATCHED_FILE: &str = "patched";
+// the artifact of the installed version is kept as `artifact-<version>`, to
+// patch it into the next one
+const ARTIFACT_FILE: &str = "artifact";
+const PACKAGE_DIR: &str = "package";
+// what is needed to go back to the previous version until the new one is healthy
+const PREVIOUS_DIR: &str = "previous";
+
+// an updated version has to call `Reactor::confirm_healthy` before it was
+// launched more than `max_launches` times, or before `timeout` has passed since
+// the install. otherwise the previous version is restored on the next start.
+#[derive(Debug, Clone, Copy)]
+pub struct HealthCheck {
+    pub max_launches: u32,
+    pub timeout: Option<Duration>,
 }
 
-impl Default for ReactorBuilder {
+impl HealthCheck {
+    fn ran_out(&self, pending: &PendingHealthCheck) -> bool {
+        let expired = self.timeout.is_some_and(|timeout| {
+            state::unix_time().saturating_sub(pending.installed_at) > timeout.as_secs()
+        });
+        pending.launches > self.max_launches || expired
+    }
+}
+
+impl Default for HealthCheck {
     fn default() -> Self {
         Self {
-            _name: Default::default(),
-            _version: Default::default(),
-            _publishing_url: Default::default(),
+            max_launches: 3,
+            timeout: None,
         }
     }
 }
 
+#[derive(Debug, Default)]
+pub struct ReactorBuilder {
+    _name: Option<String>,
+    _version: Option<Result<data::VersionTag, Error>>,
+    _publishing_url: Option<String>,
+    _trusted_keys: Vec<TrustedKey>,
+    _channel: Option<String>,
+    _channel_env: Option<String>,
+    _channel_switch_policy: ChannelSwitchPolicy,
+    _enforce_required_updates: bool,
+    _health_check: Option<HealthCheck>,
+    _retry_policy: RetryPolicy,
+    _progress_reporter: Option<Arc<dyn ProgressReporter>>,
+    _restarter: Option<Arc<dyn Restarter>>,
+    _cleanup_excluded: Vec<String>,
+    _preserved: Vec<(String, PreservePolicy)>,
+    _install_dir: Option<PathBuf>,
+    _staging_dir: Option<PathBuf>,
+    _state_dir: Option<PathBuf>,
+    _check_interval: Option<Duration>,
+    _current_exe: Option<PathBuf>,
+}
+
 impl ReactorBuilder {
     pub fn new() -> Self {
         Self {
@@ -84,31 +228,169 @@ impl ReactorBuilder {
         self
     }
     pub fn version(mut self, version: impl TryInto<VersionTag>) -> Self {
-        let version = version.try_into();
-        match version {
-            Ok(version) => self._version = Some(version),
-            Err(_) => Err(Error::InvalidLocalVersionError).unwrap(),
-        }
+        self._version = Some(
+            version
+                .try_into()
+                .map_err(|_| Error::InvalidLocalVersionError),
+        );
         self
     }
     pub fn publishing_url(mut self, publishing_url: impl Into<String>) -> Self {
         self._publishing_url = Some(publishing_url.into());
         self
     }
+    pub fn trusted_key(mut self, trusted_key: TrustedKey) -> Self {
+        self._trusted_keys.push(trusted_key);
+        self
+    }
+    pub fn channel(mut self, channel: impl Into<String>) -> Self {
+        self._channel = Some(channel.into());
+        self
+    }
+    // the channel named by this environment variable takes precedence over `channel`
+    pub fn channel_env(mut self, variable: impl Into<String>) -> Self {
+        self._channel_env = Some(variable.into());
+        self
+    }
+    pub fn channel_switch_policy(mut self, channel_switch_policy: ChannelSwitchPolicy) -> Self {
+        self._channel_switch_policy = channel_switch_policy;
+        self
+    }
+    // fails `oneclick` with `Error::UpdateRequired` while the running version is
+    // below the minimum supported version and no update could be installed.
+    // the application is expected to exit then.
+    pub fn enforce_required_updates(mut self, enforce_required_updates: bool) -> Self {
+        self._enforce_required_updates = enforce_required_updates;
+        self
+    }
+    // skips the check for updates on launches within this interval of the last
+    // check, unless the running version is no longer supported. checks on every
+    // launch by default.
+    pub fn check_interval(mut self, check_interval: Duration) -> Self {
+        self._check_interval = Some(check_interval);
+        self
+    }
+    pub fn health_check(mut self, health_check: HealthCheck) -> Self {
+        self._health_check = Some(health_check);
+        self
+    }
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self._retry_policy = retry_policy;
+        self
+    }
+    // defaults to a progress bar on stderr with the `progress-bar` feature, and
+    // to reporting nothing without it
+    pub fn progress_reporter(mut self, reporter: impl ProgressReporter + 'static) -> Self {
+        self._progress_reporter = Some(Arc::new(reporter));
+        self
+    }
+    // defaults to replacing the running process
+    pub fn restarter(mut self, restarter: impl Restarter + 'static) -> Self {
+        self._restarter = Some(Arc::new(restarter));
+        self
+    }
+    // files removed from a release are deleted on update, unless they match one
+    // of these glob patterns, e.g. `logs/**` or `*.db`
+    pub fn exclude_from_cleanup(mut self, pattern: impl Into<String>) -> Self {
+        self._cleanup_excluded.push(pattern.into());
+        self
+    }
+    // protects existing files matching the glob pattern, e.g. `config.yaml`.
+    // patterns are checked in the order they were added.
+    pub fn preserve(mut self, pattern: impl Into<String>, policy: PreservePolicy) -> Self {
+        self._preserved.push((pattern.into(), policy));
+        self
+    }
+    // defaults to the directory of the running executable
+    pub fn install_dir(mut self, install_dir: impl Into<PathBuf>) -> Self {
+        self._install_dir = Some(install_dir.into());
+        self
+    }
+    // defaults to `.reduction_react` inside the install directory
+    pub fn staging_dir(mut self, staging_dir: impl Into<PathBuf>) -> Self {
+        self._staging_dir = Some(staging_dir.into());
+        self
+    }
+    // where the updater state is kept, e.g. a user data directory when the
+    // install directory is shared. defaults to the staging directory.
+    pub fn state_dir(mut self, state_dir: impl Into<PathBuf>) -> Self {
+        self._state_dir = Some(state_dir.into());
+        self
+    }
 
-    pub fn finish(self) -> Reactor {
-        Reactor::new(
-            self._name.unwrap(),
-            self._version.unwrap(),
-            self._publishing_url.unwrap(),
-        )
+    // the executable of the running version, for applications started through
+    // a launcher. defaults to `std::env::current_exe`.
+    pub fn current_exe(mut self, current_exe: impl Into<PathBuf>) -> Self {
+        self._current_exe = Some(current_exe.into());
+        self
+    }
+
+    pub fn finish(self) -> Result<Reactor, Error> {
+        let current_exe = match self._current_exe {
+            Some(current_exe) => current_exe,
+            None => env::current_exe().map_err(Error::SelfLocationError)?,
+        };
+        let mut reactor = Reactor::with_current_exe(
+            self._name.ok_or(Error::MissingName)?,
+            self._version.ok_or(Error::MissingVersion)??,
+            self._publishing_url.ok_or(Error::MissingPublishingUrl)?,
+            current_exe,
+        )?;
+        reactor.trusted_keys = self._trusted_keys;
+        let channel_from_env = self
+            ._channel_env
+            .and_then(|variable| env::var(variable).ok())
+            .filter(|channel| !channel.is_empty());
+        if let Some(channel) = channel_from_env.or(self._channel) {
+            reactor.channel = channel;
+        }
+        reactor.channel_switch_policy = self._channel_switch_policy;
+        reactor.enforce_required_updates = self._enforce_required_updates;
+        reactor.check_interval = self._check_interval;
+        reactor.health_check = self._health_check;
+        reactor.retry_policy = self._retry_policy;
+        if let Some(reporter) = self._progress_reporter {
+            reactor.reporter = reporter;
+        }
+        if let Some(restarter) = self._restarter {
+            reactor.restarter = restarter;
+        }
+        reactor.cleanup_excluded = self._cleanup_excluded;
+        reactor.preserved = self._preserved;
+        if let Some(install_dir) = self._install_dir {
+            reactor.staging_dir = install_dir.join(STAGING_DIR);
+            reactor.install_dir = install_dir;
+        }
+        if let Some(staging_dir) = self._staging_dir {
+            reactor.staging_dir = staging_dir;
+        }
+        reactor.state_dir = self
+            ._state_dir
+            .unwrap_or_else(|| reactor.staging_dir.clone());
+        Ok(reactor)
     }
 }
 
+#[derive(Clone)]
 pub struct Reactor {
     name: String,
     version: data::VersionTag,
     pulishing_url: String,
+    trusted_keys: Vec<TrustedKey>,
+    channel: String,
+    channel_switch_policy: ChannelSwitchPolicy,
+    enforce_required_updates: bool,
+    check_interval: Option<Duration>,
+    health_check: Option<HealthCheck>,
+    retry_policy: RetryPolicy,
+    reporter: Arc<dyn ProgressReporter>,
+    restarter: Arc<dyn Restarter>,
+    cleanup_excluded: Vec<String>,
+    preserved: Vec<(String, PreservePolicy)>,
+    current_exe: PathBuf,
+    install_dir: PathBuf,
+    staging_dir: PathBuf,
+    state_dir: PathBuf,
 }
 
 impl Reactor {
@@ -116,92 +398,475 @@ impl Reactor {
         name: impl Into<String>,
         version: impl TryInto<VersionTag>,
         pulishing_url: impl Into<String>,
-    ) -> Self {
-        let version = version.try_into();
-        if version.is_err() {
-            panic!("invalid version");
-        }
-        let version = version.unwrap_or_else(|_| VersionTag::new(0, 0, 0));
-        let self_name = env::current_exe().unwrap();
-        let mut self_version = self_name.file_name().unwrap().to_str().unwrap().split("-");
-        if let Some(name_version) = self_version.nth(1) {
-            let name_version: VersionTag = name_version.try_into().unwrap();
-            if version != name_version {
-                panic!("invalid version");
+    ) -> Result<Self, Error> {
+        let version: VersionTag = version
+            .try_into()
+            .map_err(|_| Error::InvalidLocalVersionError)?;
+        let current_exe = env::current_exe().map_err(Error::SelfLocationError)?;
+        Self::with_current_exe(name.into(), version, pulishing_url.into(), current_exe)
+    }
+
+    fn with_current_exe(
+        name: String,
+        version: VersionTag,
+        pulishing_url: String,
+        current_exe: PathBuf,
+    ) -> Result<Self, Error> {
+        let self_version = current_exe
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .and_then(utils::split_versioned_file_name);
+        if let Some((file_name, name_version)) = self_version {
+            if file_name == name
+                && VersionTag::try_from(name_version).ok().as_ref() != Some(&version)
+            {
+                return Err(Error::ExecutableVersionMismatch {
+                    expected: version.to_string(),
+                    found: name_version.to_string(),
+                });
             }
         }
-        Reactor {
-            name: name.into(),
-            version: version,
-            pulishing_url: pulishing_url.into(),
-        }
+        let install_dir = current_exe
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+        Ok(Reactor {
+            name,
+            version,
+            pulishing_url,
+            trusted_keys: vec![],
+            channel: data::DEFAULT_CHANNEL.to_string(),
+            channel_switch_policy: ChannelSwitchPolicy::default(),
+            enforce_required_updates: false,
+            check_interval: None,
+            health_check: None,
+            retry_policy: RetryPolicy::default(),
+            reporter: progress::default_reporter(),
+            restarter: Arc::new(restart::ProcessRestarter),
+            cleanup_excluded: vec![],
+            preserved: vec![],
+            current_exe,
+            staging_dir: install_dir.join(STAGING_DIR),
+            state_dir: install_dir.join(STAGING_DIR),
+            install_dir,
+        })
+    }
+
+    pub fn install_dir(&self) -> &Path {
+        &self.install_dir
+    }
+
+    pub fn staging_dir(&self) -> &Path {
+        &self.staging_dir
+    }
+
+    pub fn state_dir(&self) -> &Path {
+        &self.state_dir
+    }
+
+    fn snapshot_dir(&self) -> PathBuf {
+        self.staging_dir.join(PREVIOUS_DIR)
+    }
+
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    pub fn set_channel(&mut self, channel: impl Into<String>) {
+        self.channel = channel.into();
+    }
+
+    pub fn set_channel_switch_policy(&mut self, channel_switch_policy: ChannelSwitchPolicy) {
+        self.channel_switch_policy = channel_switch_policy;
     }
 
     pub fn oneclick(&self) -> Result<(), Error> {
         info!("starting checking update");
-        self.self_update_if_available()?;
-        self.check_update_and_update()?;
+        self.recover()?;
+        // nothing is left to do once another version has been started
+        if self.self_update_if_available()?
+            || self.check_health()?
+            || self.check_update_and_update()?
+        {
+            return Ok(());
+        }
         self.self_update_if_available()?;
         info!("finshed checking update");
 
         Ok(())
     }
 
-    fn check_update_and_update(&self) -> Result<(), Error> {
-        let checker = UpdateChecker::new(self.version, &self.pulishing_url);
-        let latest_version = checker.check_update()?;
-        if let CheckUpdateResult::UpdateAvailable(latest_version) = latest_version {
-            self.update(&latest_version)?;
-        } else {
-            info!("{} is up to date", self.name);
-        }
+    // reverts an update that was interrupted before it was fully installed
+    pub fn recover(&self) -> Result<(), Error> {
+        install::recover(&self.install_dir, &self.staging_dir)?;
+        Ok(())
+    }
 
+    // tells the updater that this version works. without it, an updated version
+    // is reverted once the configured `HealthCheck` runs out.
+    pub fn confirm_healthy(&self) -> Result<(), Error> {
+        let confirmed = UpdaterState::update(&self.state_dir, |state| {
+            state
+                .pending_health_check
+                .take_if(|pending| pending.version == self.version)
+                .is_some()
+        })?;
+        if !confirmed {
+            return Ok(());
+        }
+        let snapshot_dir = self.snapshot_dir();
+        if snapshot_dir.exists() {
+            fs::remove_dir_all(&snapshot_dir).map_err(|err| Error::CommonFileError {
+                message: format!("failed to remove snapshot `{:?}`", &snapshot_dir),
+                source: err,
+            })?;
+        }
+        info!("{} {} confirmed healthy", self.name, self.version);
         Ok(())
     }
 
-    fn self_update_if_available(&self) -> Result<(), Error> {
-        thread::sleep(Duration::from_secs(1));
+    // counts a launch of an unconfirmed version, and goes back to the previous
+    // version once it ran out of launches or time. returns whether the previous
+    // version was started.
+    pub fn check_health(&self) -> Result<bool, Error> {
+        let health_check = match self.health_check {
+            Some(health_check) => health_check,
+            None => return Ok(false),
+        };
+        let ran_out = UpdaterState::update(&self.state_dir, |state| {
+            let pending = state
+                .pending_health_check
+                .as_mut()
+                .filter(|pending| pending.version == self.version)?;
+            if pending.handed_over {
+                pending.handed_over = false;
+            } else {
+                pending.launches += 1;
+            }
+            Some(health_check.ran_out(pending))
+        })?;
+        if ran_out != Some(true) {
+            return Ok(false);
+        }
+        self.give_up(&self.version)
+    }
+
+    // reverts the unconfirmed `version`. returns whether the previous version
+    // was started.
+    fn give_up(&self, version: &VersionTag) -> Result<bool, Error> {
+        let pending = UpdaterState::update(&self.state_dir, |state| {
+            let pending = state
+                .pending_health_check
+                .take_if(|pending| pending.version == *version)?;
+            state.policy.skip(pending.version.clone());
+            state.record_install(pending.previous_version.clone(), pending.version.clone());
+            Some(pending)
+        })?;
+        let pending = match pending {
+            Some(pending) => pending,
+            None => return Ok(false),
+        };
+        warn!(
+            "{} {} was never confirmed healthy, reverting to {}",
+            self.name, pending.version, pending.previous_version
+        );
+        self.revert_to(&pending.previous_version, &pending.version)
+    }
+
+    fn revert_to(
+        &self,
+        previous_version: &VersionTag,
+        failed_version: &VersionTag,
+    ) -> Result<bool, Error> {
+        let snapshot_dir = self.snapshot_dir();
+        install::recover(&self.install_dir, &snapshot_dir)?;
+        files::copy_installed(&snapshot_dir, &self.staging_dir)?;
+        // nothing to start when the previous version is the one running
+        let restart = *previous_version != self.version;
+        if restart {
+            let file_name = self.versioned_file_name(previous_version);
+            let previous_exe = snapshot_dir.join(&file_name);
+            fs::copy(&previous_exe, self.install_dir.join(&file_name)).map_err(|err| {
+                Error::CommonFileError {
+                    message: format!(
+                        "failed to restore previous executable `{:?}`",
+                        &previous_exe
+                    ),
+                    source: err,
+                }
+            })?;
+        }
+        if let Err(err) = fs::remove_dir_all(&snapshot_dir) {
+            warn!("failed to remove snapshot `{:?}`: {}", &snapshot_dir, err);
+        }
+        // otherwise `self_update_if_available` would start it again
+        let failed_exe = self
+            .install_dir
+            .join(self.versioned_file_name(failed_version));
+        if failed_exe.exists() {
+            if let Err(err) = fs::remove_file(&failed_exe) {
+                warn!("failed to remove `{:?}`: {}", &failed_exe, err);
+            }
+        }
+        if !restart {
+            return Ok(false);
+        }
+        self.restart_into(previous_version)
+    }
+
+    fn versioned_file_name(&self, version: &VersionTag) -> String {
+        format!("{}-{}{}", self.name, version, env::consts::EXE_SUFFIX)
+    }
+
+    // identifies this install in staged rollouts. it is created on first use
+    // and kept in the updater state.
+    pub fn client_id(&self) -> Result<String, Error> {
+        Ok(self.checking_state()?.client_id.unwrap_or_default())
+    }
+
+    // the update policy chosen by the user, e.g. for a settings screen
+    pub fn update_policy(&self) -> Result<UpdatePolicy, Error> {
+        Ok(UpdaterState::load(&self.state_dir)?.policy)
+    }
+
+    // applies from the next check on
+    pub fn set_update_policy(&self, policy: UpdatePolicy) -> Result<(), Error> {
+        UpdaterState::update(&self.state_dir, |state| state.policy = policy)
+    }
+
+    // the versions installed by the updater, oldest first
+    pub fn installed_versions(&self) -> Result<Vec<InstalledVersion>, Error> {
+        Ok(UpdaterState::load(&self.state_dir)?.installed_versions)
+    }
+
+    // when the publisher was last asked for updates
+    pub fn last_check(&self) -> Result<Option<SystemTime>, Error> {
+        Ok(UpdaterState::load(&self.state_dir)?
+            .last_check
+            .map(|last_check| UNIX_EPOCH + Duration::from_secs(last_check)))
+    }
+
+    fn checking_state(&self) -> Result<UpdaterState, Error> {
+        UpdaterState::update(&self.state_dir, |state| {
+            state.ensure_client_id()?;
+            Ok(state.clone())
+        })?
+    }
+
+    fn checker<'a>(&'a self, state: &'a UpdaterState) -> UpdateChecker<'a> {
+        let checker = UpdateChecker::new(self.version.clone(), &self.pulishing_url)
+            .trusted_keys(&self.trusted_keys)
+            .channel(&self.channel)
+            .channel_switch_policy(self.channel_switch_policy)
+            .policy(&state.policy);
+        let checker = match &state.manifest {
+            Some(manifest) => checker.cached_manifest(manifest),
+            None => checker,
+        };
+        match &state.client_id {
+            Some(client_id) => checker.client_id(client_id),
+            None => checker,
+        }
+    }
 
+    // whether the last check is recent enough to skip this one. an unsupported
+    // running version checks on every launch.
+    fn skips_check(&self, state: &UpdaterState) -> bool {
+        let (Some(check_interval), Some(last_check), Some(manifest)) = (
+            self.check_interval,
+            state.last_check,
+            self.cached_manifest(state),
+        ) else {
+            return false;
+        };
+        let now = state::unix_time();
+        if now < last_check || now - last_check >= check_interval.as_secs() {
+            return false;
+        }
+        self.checker(state)
+            .minimum_version(&manifest)
+            .is_none_or(|minimum_version| self.version >= minimum_version)
+    }
+
+    // the manifest seen by the last check
+    fn cached_manifest(&self, state: &UpdaterState) -> Option<Manifest> {
+        let manifest = state
+            .manifest
+            .as_ref()
+            .filter(|manifest| manifest.url == self.pulishing_url)?;
+        self.checker(state).read_manifest(manifest).ok()
+    }
+
+    // a check that failed, e.g. while offline, still stops a version that the
+    // last seen manifest declared unsupported
+    fn check_failed(&self, state: &UpdaterState, err: Error) -> Error {
+        let minimum_version = self
+            .cached_manifest(state)
+            .and_then(|manifest| self.checker(state).minimum_version(&manifest));
+        match minimum_version {
+            Some(minimum_version)
+                if self.enforce_required_updates && self.version < minimum_version =>
+            {
+                Error::UpdateRequired {
+                    version: self.version.to_string(),
+                    minimum: minimum_version.to_string(),
+                    source: Some(Box::new(err)),
+                }
+            }
+            _ => err,
+        }
+    }
+
+    // only after a check went through, so that a failed update is retried on
+    // the next launch
+    fn record_check(&self) -> Result<(), Error> {
+        UpdaterState::update(&self.state_dir, |state| {
+            state.last_check = Some(state::unix_time())
+        })
+    }
+
+    // picks the release to install from a check result. the flag tells whether
+    // it is a downgrade.
+    fn select_update(&self, result: CheckUpdateResult) -> Option<(PackageTag, bool)> {
+        match result {
+            CheckUpdateResult::UpdateAvailable(latest_version, urgency) => {
+                info!(
+                    "{} {} is available ({:?})",
+                    self.name, latest_version.version, urgency
+                );
+                Some((latest_version, false))
+            }
+            CheckUpdateResult::DowngradeAvailable(latest_version) => {
+                warn!(
+                    "switching to {} {} on channel `{}`",
+                    self.name, latest_version.version, self.channel
+                );
+                Some((latest_version, true))
+            }
+            CheckUpdateResult::NoArtifactForTarget(latest_version) => {
+                warn!(
+                    "{} {} is available, but not for this target",
+                    self.name,
+                    latest_version.version.as_string()
+                );
+                None
+            }
+            CheckUpdateResult::UpToDate => {
+                info!("{} is up to date", self.name);
+                None
+            }
+        }
+    }
+
+    // returns whether the installed version was started
+    fn check_update_and_update(&self) -> Result<bool, Error> {
+        let mut state = self.checking_state()?;
+        if self.skips_check(&state) {
+            info!("checked for updates recently, skipping");
+            return Ok(false);
+        }
+        self.reporter.phase(Phase::Checking);
+        let checker = self.checker(&state);
+        let (fetched, manifest) = checker
+            .fetch_manifest()
+            .and_then(|fetched| {
+                let manifest = checker.read_manifest(&fetched)?;
+                Ok((fetched, manifest))
+            })
+            .map_err(|err| self.check_failed(&state, err))?;
+        state.manifest = Some(fetched);
+        UpdaterState::update(&self.state_dir, |saved| {
+            saved.manifest = state.manifest.clone()
+        })?;
+        let checker = self.checker(&state);
+        let minimum_version = checker.minimum_version(&manifest);
+        let selected = self.select_update(checker.evaluate(&manifest)?);
+        let installed = match &selected {
+            Some((latest_version, _)) => self.update(latest_version).map(|()| true),
+            None => Ok(false),
+        };
+        self.require_supported(minimum_version, installed)?;
+        self.record_check()?;
+        if let Some((latest_version, true)) = selected {
+            // older versions are never picked up by `self_update_if_available`,
+            // so start the installed one directly
+            return self.restart_into(&latest_version.version);
+        }
+
+        Ok(false)
+    }
+
+    // passes on the outcome of installing an update, unless the running version
+    // is unsupported and has not been replaced
+    fn require_supported(
+        &self,
+        minimum_version: Option<VersionTag>,
+        installed: Result<bool, Error>,
+    ) -> Result<(), Error> {
+        let minimum_version = match minimum_version {
+            Some(minimum_version) if self.version < minimum_version => minimum_version,
+            _ => return installed.map(|_| ()),
+        };
+        let err = match installed {
+            Ok(true) => return Ok(()),
+            Ok(false) => None,
+            Err(err) => Some(Box::new(err)),
+        };
+        if !self.enforce_required_updates {
+            warn!(
+                "{} {} is no longer supported, at least {} is required",
+                self.name, self.version, minimum_version
+            );
+            return err.map_or(Ok(()), |err| Err(*err));
+        }
+        Err(Error::UpdateRequired {
+            version: self.version.to_string(),
+            minimum: minimum_version.to_string(),
+            source: err,
+        })
+    }
+
+    // starts a newer local version, or makes this one the default executable.
+    // returns whether another executable was started.
+    fn self_update_if_available(&self) -> Result<bool, Error> {
         let mut other_version = self.find_other_available_versions()?;
+        let is_versioned = self
+            .current_exe
+            .file_name()
+            .is_some_and(|file_name| file_name.to_string_lossy().contains('-'));
+        if other_version.is_empty() && !is_versioned {
+            return Ok(false);
+        }
+        // the version that started this one may not have exited yet
+        thread::sleep(Duration::from_secs(1));
+
         other_version.sort();
         // start latest version
         other_version.reverse();
-        if let Some(new_version) = other_version.get(0) {
+        if let Some(new_version) = other_version.first() {
             if new_version.0 > self.version {
-                #[cfg(not(windows))]
-                {
-                    use std::os::unix::prelude::PermissionsExt;
-                    fs::set_permissions(&new_version.1, Permissions::from_mode(0o755))
-                        .map_err(|err| Error::PermissionError(err))?;
-                }
                 warn!(
                     "found new local version: {:?}. restarting...",
                     new_version.0
                 );
-                run_executable_and_quit(new_version.1.canonicalize().unwrap());
+                if self.start(&new_version.0, &new_version.1)? {
+                    return Ok(true);
+                }
             }
         }
 
         // make self as default executable
-        let cur_path = std::env::current_exe().map_err(|err| Error::SelfLocationError(err))?;
-        if cur_path
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .find("-")
-            .is_some()
-        {
-            let new_path = cur_path
-                .parent()
-                .unwrap()
+        let cur_path = &self.current_exe;
+        if is_versioned {
+            let new_path = self
+                .install_dir
                 .join(utils::get_executable_file_name(&self.name)?);
-            fs::copy(&cur_path, &new_path).map_err(|err| Error::CommonFileError {
-                message: format!("failed to set current version as default executable"),
+            fs::copy(cur_path, &new_path).map_err(|err| Error::CommonFileError {
+                message: "failed to set current version as default executable".to_string(),
                 source: err,
             })?;
             warn!("replaced default version. restarting...");
-            run_executable_and_quit(new_path.canonicalize().unwrap());
+            return self.restart(&new_path);
         }
 
         other_version.sort();
@@ -216,26 +881,77 @@ impl Reactor {
             }
         }
 
-        Ok(())
+        Ok(false)
+    }
+
+    fn restart_into(&self, version: &VersionTag) -> Result<bool, Error> {
+        let path = self.install_dir.join(self.versioned_file_name(version));
+        if !path.exists() {
+            warn!("installed package does not contain `{:?}`", path);
+            return Ok(false);
+        }
+        warn!("installed version: {}. restarting...", version);
+        self.start(version, &path)
+    }
+
+    // starts the executable of another version. the launch of an unconfirmed
+    // version is counted here, as a version that fails early never gets to
+    // count it itself. returns whether another executable was started.
+    fn start(&self, version: &VersionTag, executable: &Path) -> Result<bool, Error> {
+        if let Some(health_check) = self.health_check {
+            let ran_out = UpdaterState::update(&self.state_dir, |state| {
+                let pending = state
+                    .pending_health_check
+                    .as_mut()
+                    .filter(|pending| pending.version == *version)?;
+                pending.launches += 1;
+                pending.handed_over = true;
+                Some(health_check.ran_out(pending))
+            })?;
+            if ran_out == Some(true) {
+                return self.give_up(version);
+            }
+        }
+        #[cfg(not(windows))]
+        {
+            use std::os::unix::prelude::PermissionsExt;
+            fs::set_permissions(executable, Permissions::from_mode(0o755))
+                .map_err(Error::PermissionError)?;
+        }
+        self.restart(executable)
+    }
+
+    fn restart(&self, executable: &Path) -> Result<bool, Error> {
+        let executable = executable
+            .canonicalize()
+            .map_err(|err| Error::CommonFileError {
+                message: format!("failed to locate `{:?}`", executable),
+                source: err,
+            })?;
+        self.reporter.phase(Phase::Restarting);
+        self.restarter.restart(&executable)?;
+        Ok(true)
     }
 
     fn find_other_available_versions(&self) -> Result<Vec<(VersionTag, PathBuf)>, Error> {
-        let paths = fs::read_dir(".").map_err(|err| Error::CommonFileError {
-            message: format!("failed to read current directory"),
+        let paths = fs::read_dir(&self.install_dir).map_err(|err| Error::CommonFileError {
+            message: format!("failed to read install directory `{:?}`", &self.install_dir),
             source: err,
         })?;
 
         let mut result = vec![];
         for path in paths {
             let path = path.map_err(|err| Error::CommonFileError {
-                message: format!("failed to read dir"),
+                message: "failed to read dir".to_string(),
                 source: err,
             })?;
+            // other files, including ones named in another encoding, are
+            // not executables of this application
             let name = path.file_name();
-            let name = name.to_str().unwrap();
-            if name.starts_with(&self.name) {
-                let file_version = name.split("-").nth(1);
-                if let Some(file_version) = file_version {
+            if let Some((file_name, file_version)) =
+                name.to_str().and_then(utils::split_versioned_file_name)
+            {
+                if file_name == self.name {
                     let file_version = file_version.try_into();
                     if let Ok(version_tag) = file_version {
                         result.push((version_tag, path.path()));
@@ -246,64 +962,407 @@ impl Reactor {
         Ok(result)
     }
 
-    pub fn update(&self, package_tag: &PackageTag) -> Result<(), Error> {
-        // update lib
-        utils::download_file(&package_tag.download_url, "temp.zip")?;
-        // TODO: check hash
-        let temp_dir = PathBuf::from("./temp");
-        if temp_dir.exists() {
-            std::fs::remove_dir_all(&temp_dir).map_err(|err| Error::CommonFileError {
-                message: format!("failed to clear temp directory `temp`"),
+    fn expect_health_confirmation(
+        &self,
+        package_tag: &PackageTag,
+        snapshot_dir: &Path,
+    ) -> Result<(), Error> {
+        // the running executable is replaced during the restart, keep it around
+        let previous_exe = snapshot_dir.join(self.versioned_file_name(&self.version));
+        fs::copy(&self.current_exe, &previous_exe).map_err(|err| Error::CommonFileError {
+            message: format!(
+                "failed to keep previous executable `{:?}`",
+                &self.current_exe
+            ),
+            source: err,
+        })?;
+        UpdaterState::update(&self.state_dir, |state| {
+            state.pending_health_check = Some(PendingHealthCheck {
+                version: package_tag.version.clone(),
+                previous_version: self.version.clone(),
+                installed_at: state::unix_time(),
+                launches: 0,
+                handed_over: false,
+            })
+        })
+    }
+
+    // obsolete versioned executables are left to `self_update_if_available`
+    fn plan_install(
+        &self,
+        package_files: &FileManifest,
+    ) -> Result<(InstallPlan, FileManifest), Error> {
+        let installed_files = FileManifest::load(&self.staging_dir)?;
+        let (mut plan, left_installed) = files::plan_install(
+            &self.install_dir,
+            package_files,
+            installed_files.as_ref(),
+            &self.preserved,
+            &self.cleanup_excluded,
+        )?;
+        let is_executable = |path: &Path| {
+            path.parent() == Some(Path::new(""))
+                && path
+                    .to_str()
+                    .and_then(utils::split_versioned_file_name)
+                    .is_some_and(|(name, _)| name == self.name)
+        };
+        plan.obsolete.retain(|path| !is_executable(path));
+        Ok((plan, left_installed))
+    }
+
+    fn prepare_download(&self, package_tag: &PackageTag) -> Result<Download, Error> {
+        let target = TargetInformation::current();
+        let artifact =
+            package_tag
+                .artifact_for(&target)
+                .ok_or_else(|| Error::NoArtifactForTarget {
+                    version: package_tag.version.as_string(),
+                    target: target.to_string(),
+                })?;
+        fs::create_dir_all(&self.staging_dir).map_err(|err| Error::CommonFileError {
+            message: format!(
+                "failed to create staging directory `{:?}`",
+                &self.staging_dir
+            ),
+            source: err,
+        })?;
+        // patches apply to the artifact of the running version. a raw one is
+        // the running executable itself.
+        let kept_artifact = self.kept_artifact(&self.version);
+        let base = if kept_artifact.exists() {
+            Some(kept_artifact)
+        } else if artifact
+            .format
+            .is_none_or(|format| format == ArchiveFormat::Raw)
+        {
+            Some(self.current_exe.clone())
+        } else {
+            None
+        };
+        let patches = match &base {
+            Some(_) => UpdateChecker::new(self.version.clone(), &self.pulishing_url)
+                .patch_chain(package_tag)
+                .unwrap_or_default(),
+            None => vec![],
+        };
+        Ok(Download {
+            expected_hash: PackageHash::try_from(artifact.hash.as_str())?,
+            patches,
+            base,
+            format: artifact.format,
+            files: Some(FileManifest {
+                files: artifact.files,
+            })
+            .filter(|files| !files.files.is_empty()),
+            url: artifact.download_url,
+            path: self.staging_dir.join(DOWNLOAD_FILE),
+        })
+    }
+
+    fn kept_artifact(&self, version: &VersionTag) -> PathBuf {
+        self.staging_dir
+            .join(format!("{}-{}", ARTIFACT_FILE, version))
+    }
+
+    // keeps an installed archive as the base of the next patch, in place of the
+    // one kept before. raw artifacts are the executable itself.
+    fn keep_artifact(
+        &self,
+        format: ArchiveFormat,
+        download_path: &Path,
+        version: &VersionTag,
+    ) -> Result<(), Error> {
+        let entries = fs::read_dir(&self.staging_dir).map_err(|err| Error::CommonFileError {
+            message: format!("failed to read staging directory `{:?}`", &self.staging_dir),
+            source: err,
+        })?;
+        let prefix = format!("{}-", ARTIFACT_FILE);
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                fs::remove_file(entry.path()).map_err(|err| Error::CommonFileError {
+                    message: format!("failed to remove kept artifact `{:?}`", entry.path()),
+                    source: err,
+                })?;
+            }
+        }
+        if format == ArchiveFormat::Raw {
+            return fs::remove_file(download_path).map_err(|err| Error::CommonFileError {
+                message: format!("failed to remove downloaded file `{:?}`", download_path),
+                source: err,
+            });
+        }
+        let kept_artifact = self.kept_artifact(version);
+        fs::rename(download_path, &kept_artifact).map_err(|err| Error::CommonFileError {
+            message: format!("failed to keep downloaded file `{:?}`", download_path),
+            source: err,
+        })
+    }
+
+    fn install_download(
+        &self,
+        package_tag: &PackageTag,
+        download: &Download,
+        actual_hash: &PackageHash,
+    ) -> Result<(), Error> {
+        let download_path = &download.path;
+        let package_dir = self.staging_dir.join(PACKAGE_DIR);
+        self.reporter.phase(Phase::Verifying);
+        if let Err(err) = download.expected_hash.verify(actual_hash) {
+            warn!("downloaded package is corrupted: {}", err);
+            download::discard(download_path)?;
+            return Err(err);
+        }
+        info!("verified remote package");
+        if package_dir.exists() {
+            fs::remove_dir_all(&package_dir).map_err(|err| Error::CommonFileError {
+                message: format!("failed to clear package directory `{:?}`", &package_dir),
                 source: err,
             })?;
         }
-        utils::extract_zip("temp.zip", &temp_dir)?;
+        self.reporter.phase(Phase::Extracting);
+        let format = match download.format {
+            Some(format) => format,
+            None => ArchiveFormat::detect(download_path)?,
+        };
+        archive::extract(
+            format,
+            download_path,
+            &package_dir,
+            &self.versioned_file_name(&package_tag.version),
+        )?;
         info!("extracted remote package");
-        utils::copy(&temp_dir, ".").map_err(|err| Error::CommonFileError {
-            message: format!("failed to copy directories `{:?}`", &temp_dir),
-            source: err,
-        })?;
+        let snapshot_dir = self.health_check.map(|_| self.snapshot_dir());
+        self.reporter.phase(Phase::Installing);
+        let package_files = match &download.files {
+            Some(published) => {
+                if let Err(err) = published.verify(&package_dir) {
+                    warn!("extracted package does not match the release: {}", err);
+                    download::discard(download_path)?;
+                    return Err(err);
+                }
+                published.clone()
+            }
+            // releases published without their files
+            None => FileManifest::from_dir(&package_dir)?,
+        };
+        let (plan, installed_files) = self.plan_install(&package_files)?;
+        install::install(
+            &package_dir,
+            &self.install_dir,
+            &self.staging_dir,
+            snapshot_dir.as_deref(),
+            &plan,
+        )?;
         info!("replaced old data with new data");
-        std::fs::remove_dir_all(temp_dir).map_err(|err| Error::CommonFileError {
-            message: format!("failed to remove temp directory `temp`"),
-            source: err,
+        if let Some(snapshot_dir) = &snapshot_dir {
+            files::copy_installed(&self.staging_dir, snapshot_dir)?;
+        }
+        installed_files.save(&self.staging_dir)?;
+        UpdaterState::update(&self.state_dir, |state| {
+            state.record_install(package_tag.version.clone(), self.version.clone())
         })?;
-        std::fs::remove_file("temp.zip").map_err(|err| Error::CommonFileError {
-            message: format!("failed to remove temp file `temp.zip`"),
+        if let Some(snapshot_dir) = snapshot_dir {
+            self.expect_health_confirmation(package_tag, &snapshot_dir)?;
+        }
+        fs::remove_dir_all(&package_dir).map_err(|err| Error::CommonFileError {
+            message: format!("failed to remove package directory `{:?}`", &package_dir),
             source: err,
         })?;
+        self.keep_artifact(format, download_path, &package_tag.version)?;
         info!("finish file updates");
 
         Ok(())
     }
-}
 
-#[cfg(unix)]
-fn run_executable_and_quit(path: impl AsRef<Path>) {
-    use std::os::unix::prelude::CommandExt;
-    std::process::Command::new(path.as_ref().to_str().unwrap()).exec();
+    // rebuilds the artifact from the one of the running version and the patch
+    // chain of the download. returns `None` when the full artifact has to be
+    // downloaded, leaving a partial download to be resumed.
+    fn try_patch(&self, download: &Download) -> Result<Option<PackageHash>, Error> {
+        let base = match &download.base {
+            Some(base) if !download.patches.is_empty() => base,
+            _ => return Ok(None),
+        };
+        let patched_path = self.staging_dir.join(PATCHED_FILE);
+        match self.apply_patches(download, base, &patched_path) {
+            Ok(actual_hash) if download.expected_hash.verify(&actual_hash).is_ok() => {
+                info!("rebuilt release from {} patches", download.patches.len());
+                download::discard(&download.path)?;
+                fs::rename(&patched_path, &download.path).map_err(|err| {
+                    Error::CommonFileError {
+                        message: format!("failed to move patched file `{:?}`", &patched_path),
+                        source: err,
+                    }
+                })?;
+                return Ok(Some(actual_hash));
+            }
+            Ok(actual_hash) => warn!(
+                "patched executable has hash `{}` instead of `{}`, downloading the full release",
+                actual_hash, download.expected_hash
+            ),
+            Err(err) => warn!(
+                "failed to patch executable, downloading the full release: {}",
+                err
+            ),
+        }
+        download::discard(&patched_path)?;
+        Ok(None)
+    }
+
+    fn apply_patches(
+        &self,
+        download: &Download,
+        base: &Path,
+        patched_path: &Path,
+    ) -> Result<PackageHash, Error> {
+        let mut data = fs::read(base).map_err(|err| Error::CommonFileError {
+            message: format!("failed to read patch base `{:?}`", base),
+            source: err,
+        })?;
+        let patch_path = self.staging_dir.join(PATCH_FILE);
+        for Patch {
+            from,
+            to,
+            hash,
+            download_url,
+        } in download.patches.iter()
+        {
+            trace!("downloading patch from {} to {}", from, to);
+            let expected_hash = PackageHash::try_from(hash.as_str())?;
+            let actual_hash = download::download_file(
+                download_url,
+                &patch_path,
+                expected_hash.algorithm(),
+                &self.retry_policy,
+                self.reporter.as_ref(),
+            )?;
+            let patch = fs::read(&patch_path).map_err(|err| Error::CommonFileError {
+                message: format!("failed to read patch `{:?}`", &patch_path),
+                source: err,
+            });
+            download::discard(&patch_path)?;
+            expected_hash.verify(&actual_hash)?;
+            data = delta::apply(&data, &patch?)?;
+        }
+        fs::write(patched_path, &data).map_err(|err| Error::CommonFileError {
+            message: format!("failed to write patched file `{:?}`", patched_path),
+            source: err,
+        })?;
+        let mut hasher = download.expected_hash.algorithm().hasher();
+        hasher.update(&data);
+        Ok(hasher.finalize())
+    }
+
+    pub fn update(&self, package_tag: &PackageTag) -> Result<(), Error> {
+        let download = self.prepare_download(package_tag)?;
+        self.reporter.phase(Phase::Downloading);
+        let actual_hash = match self.try_patch(&download)? {
+            Some(actual_hash) => actual_hash,
+            None => download::download_file(
+                &download.url,
+                &download.path,
+                download.expected_hash.algorithm(),
+                &self.retry_policy,
+                self.reporter.as_ref(),
+            )?,
+        };
+        self.install_download(package_tag, &download, &actual_hash)
+    }
 }
 
-#[cfg(windows)]
-fn run_executable_and_quit(path: impl AsRef<Path>) {
-    std::process::Command::new("cmd")
-        .arg("/C")
-        .arg("start")
-        .arg("")
-        .arg(path.as_ref().to_str().unwrap())
-        .output()
-        .unwrap();
-    std::process::exit(0);
+// an artifact that is about to be downloaded into the staging directory
+#[derive(Debug, Clone)]
+struct Download {
+    url: String,
+    // applied to `base` instead of downloading `url`
+    patches: Vec<Patch>,
+    base: Option<PathBuf>,
+    format: Option<ArchiveFormat>,
+    // the published files of the package, verified after extraction
+    files: Option<FileManifest>,
+    path: PathBuf,
+    expected_hash: PackageHash,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     #[test]
+    fn test_builder() {
+        let reactor = ReactorBuilder::new()
+            .name("hello")
+            .version("1.0.4")
+            .publishing_url("http://127.0.0.1:8080/hello.json")
+            .finish()
+            .unwrap();
+        assert_eq!(reactor.version, VersionTag::new(1, 0, 4));
+
+        assert!(matches!(
+            ReactorBuilder::new().version("1.0.4").finish(),
+            Err(Error::MissingName)
+        ));
+        assert!(matches!(
+            ReactorBuilder::new()
+                .name("hello")
+                .publishing_url("url")
+                .finish(),
+            Err(Error::MissingVersion)
+        ));
+        assert!(matches!(
+            ReactorBuilder::new()
+                .name("hello")
+                .version("1.0.4")
+                .finish(),
+            Err(Error::MissingPublishingUrl)
+        ));
+        assert!(matches!(
+            ReactorBuilder::new()
+                .name("hello")
+                .version("1.0")
+                .publishing_url("url")
+                .finish(),
+            Err(Error::InvalidLocalVersionError)
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_odd_file_names() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            OsStr::from_bytes(b"hello-\xff.so"),
+            OsStr::new("hello-notes.txt"),
+            OsStr::new("other-2.0.0"),
+            OsStr::new("hello-2.0.0"),
+        ] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        let reactor = ReactorBuilder::new()
+            .name("hello")
+            .version("1.0.0")
+            .publishing_url("http://127.0.0.1:8080/hello.json")
+            .install_dir(dir.path())
+            .current_exe(dir.path().join(OsStr::from_bytes(b"hello\xff")))
+            .finish()
+            .unwrap();
+        assert_eq!(
+            reactor.find_other_available_versions().unwrap(),
+            vec![(VersionTag::new(2, 0, 0), dir.path().join("hello-2.0.0"))]
+        );
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
     fn test_unzip() {
-        let path = PathBuf::from("test.zip");
-        let dest = PathBuf::from("./test");
-        utils::extract_zip(&path, &dest).unwrap();
-        std::fs::remove_dir_all("./test").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("build/lib")).unwrap();
+        fs::write(dir.path().join("build/lib/data.txt"), "data").unwrap();
+        let path = dir.path().join("test.zip");
+        archive::create(ArchiveFormat::Zip, dir.path().join("build"), &path).unwrap();
+        let dest = dir.path().join("test");
+        archive::extract_zip(&path, &dest).unwrap();
+        assert_eq!(fs::read(dest.join("lib/data.txt")).unwrap(), b"data");
     }
 }
@@ -1,3 +1,302 @@
+use std::{env, error::Error as _, path::PathBuf, process, time::Duration};
+
+use reduction_react::{
+    publish::{self, Release},
+    server::{Fault, ReleaseServer},
+    ArchiveFormat, Error, TrustedKey,
+};
+
+const USAGE: &str = "usage:
+    reduction_react package <build dir> <archive> [--format <format>]
+    reduction_react hash <file> [--algorithm <algorithm>]
+    reduction_react manifest <manifest> --version <version> --archive <archive> --url <download url>
+        [--channel <channel>] [--target <triple> | --os <os> --arch <arch>]
+        [--format <format>] [--algorithm <algorithm>]
+        [--date <yyyy-mm-dd>] [--changelog <changelog file>] [--mandatory]
+        [--minimum-version <version>] [--rollout <percentage>]
+    reduction_react rollout <manifest> <version> <percentage> [--channel <channel>]
+    reduction_react sign <manifest> --key <secret key file> --key-id <key id>
+    reduction_react public-key <secret key file>
+    reduction_react validate <manifest> --dir <releases dir> [--base-url <url>]
+        [--trusted-key <key id>=<public key>]...
+    reduction_react serve <releases dir> [--addr <address>] [--delay-ms <ms>]
+        [--throttle <bytes per second>] [--truncate <bytes>] [--fail-first <requests>]
+
+formats are zip, tar.gz, tar.xz, tar.zst and raw. secret keys are 32 bytes of
+hex, e.g. created by `openssl rand -hex 32`.";
+
+// options that stand alone instead of taking a value
+const FLAGS: &[&str] = &["mandatory"];
+
+// the command line after the command name: positional arguments, flags, and
+// options that take a value
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args {
+            positional: vec![],
+            options: vec![],
+        };
+        while let Some(arg) = args.next() {
+            match arg.strip_prefix("--") {
+                Some(name) if FLAGS.contains(&name) => {
+                    parsed.options.push((name.to_string(), String::new()))
+                }
+                Some(name) => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| format!("missing value for `--{}`", name))?;
+                    parsed.options.push((name.to_string(), value));
+                }
+                None => parsed.positional.push(arg),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn positional(&self, index: usize, name: &str) -> Result<&str, String> {
+        self.positional
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| format!("missing {}", name))
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(option, _)| option == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.option(name).is_some()
+    }
+
+    fn required(&self, name: &str) -> Result<&str, String> {
+        self.option(name)
+            .ok_or_else(|| format!("missing `--{}`", name))
+    }
+
+    fn all(&self, name: &str) -> impl Iterator<Item = &str> {
+        let name = name.to_string();
+        self.options
+            .iter()
+            .filter(move |(option, _)| *option == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn number(&self, name: &str) -> Result<Option<u64>, String> {
+        self.option(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("`--{}` expects a number, got `{}`", name, value))
+            })
+            .transpose()
+    }
+
+    fn format(&self) -> Result<Option<ArchiveFormat>, String> {
+        self.option("format")
+            .map(|format| {
+                ArchiveFormat::from_name(format)
+                    .ok_or_else(|| format!("unknown archive format `{}`", format))
+            })
+            .transpose()
+    }
+}
+
+fn percentage(value: &str) -> Result<u8, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|percentage| *percentage <= 100)
+        .ok_or_else(|| format!("expected a percentage up to 100, got `{}`", value))
+}
+
+// a failed command. usage errors are reported together with the usage.
+enum Failure {
+    Usage(String),
+    Failed(Error),
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Failure::Usage(message)
+    }
+}
+
+impl From<Error> for Failure {
+    fn from(err: Error) -> Self {
+        Failure::Failed(err)
+    }
+}
+
+fn run(command: &str, args: &Args) -> Result<(), Failure> {
+    match command {
+        "package" => {
+            let archive = args.positional(1, "archive")?;
+            let format =
+                publish::package(args.positional(0, "build dir")?, archive, args.format()?)?;
+            println!("packed {} archive {}", format, archive);
+        }
+        "hash" => {
+            let algorithm = args.option("algorithm").unwrap_or("sha256");
+            println!("{}", publish::hash(args.positional(0, "file")?, algorithm)?);
+        }
+        "manifest" => {
+            let manifest = args.positional(0, "manifest")?;
+            let mut release = Release::new(
+                args.option("channel").unwrap_or("stable"),
+                args.required("version")?,
+                args.required("archive")?,
+                args.required("url")?,
+            );
+            release.target = args.option("target").map(String::from);
+            release.os = args.option("os").map(String::from);
+            release.arch = args.option("arch").map(String::from);
+            if release.target.is_some() && (release.os.is_some() || release.arch.is_some()) {
+                return Err("`--target` cannot be combined with `--os` or `--arch`"
+                    .to_string()
+                    .into());
+            }
+            release.format = args.format()?;
+            if let Some(algorithm) = args.option("algorithm") {
+                release.hash_algorithm = algorithm.to_string();
+            }
+            release.date = args.option("date").map(String::from);
+            if let Some(path) = args.option("changelog") {
+                release.changelog =
+                    Some(
+                        std::fs::read_to_string(path).map_err(|err| Error::CommonFileError {
+                            message: format!("failed to read changelog `{}`", path),
+                            source: err,
+                        })?,
+                    );
+            }
+            release.mandatory = args.flag("mandatory");
+            release.rollout = args.option("rollout").map(percentage).transpose()?;
+            publish::add_release(manifest, &release)?;
+            println!(
+                "published {} on channel `{}` in {}",
+                release.version, release.channel, manifest
+            );
+            if let Some(version) = args.option("minimum-version") {
+                publish::set_minimum_version(manifest, version)?;
+                println!("{} is the minimum supported version", version);
+            }
+        }
+        "rollout" => {
+            let channel = args.option("channel").unwrap_or("stable");
+            let version = args.positional(1, "version")?;
+            let percentage = percentage(args.positional(2, "percentage")?)?;
+            publish::set_rollout(
+                args.positional(0, "manifest")?,
+                channel,
+                version,
+                percentage,
+            )?;
+            println!(
+                "rolled out {} on channel `{}` to {}% of clients",
+                version, channel, percentage
+            );
+        }
+        "sign" => {
+            let signature = publish::sign(
+                args.positional(0, "manifest")?,
+                args.required("key-id")?,
+                args.required("key")?,
+            )?;
+            println!("wrote signature {}", signature.display());
+        }
+        "public-key" => {
+            println!(
+                "{}",
+                publish::public_key(args.positional(0, "secret key file")?)?
+            );
+        }
+        "validate" => {
+            let manifest = args.positional(0, "manifest")?;
+            let trusted_keys = args
+                .all("trusted-key")
+                .map(|trusted_key| {
+                    let (key_id, public_key) = trusted_key.split_once('=').ok_or_else(|| {
+                        format!("expected `<key id>=<public key>`, got `{}`", trusted_key)
+                    })?;
+                    Ok(TrustedKey::from_hex(key_id, public_key)?)
+                })
+                .collect::<Result<Vec<_>, Failure>>()?;
+            let problems = publish::validate(
+                manifest,
+                PathBuf::from(args.required("dir")?),
+                args.option("base-url"),
+                &trusted_keys,
+            )?;
+            if !problems.is_empty() {
+                for problem in problems.iter() {
+                    eprintln!("{}", problem);
+                }
+                eprintln!("{} has {} problems", manifest, problems.len());
+                process::exit(1);
+            }
+            println!("{} is valid", manifest);
+        }
+        "serve" => {
+            let server = ReleaseServer::bind(
+                args.positional(0, "releases dir")?,
+                args.option("addr").unwrap_or("127.0.0.1:8080"),
+            )?;
+            if let Some(delay) = args.number("delay-ms")? {
+                server.fault_always(Fault::Delay(Duration::from_millis(delay)));
+            }
+            if let Some(bytes_per_second) = args.number("throttle")? {
+                server.fault_always(Fault::Throttle(bytes_per_second));
+            }
+            if let Some(len) = args.number("truncate")? {
+                server.fault_always(Fault::Truncate(len));
+            }
+            for _ in 0..args.number("fail-first")?.unwrap_or(0) {
+                server.fault_next(Fault::ServerError);
+            }
+            println!("serving releases at {}", server.url(""));
+            server.join();
+        }
+        command => return Err(format!("unknown command `{}`", command).into()),
+    }
+    Ok(())
+}
+
 fn main() {
-    println!("Hello, world!");
+    let mut args = env::args().skip(1);
+    let command = match args.next() {
+        Some(command) if command != "--help" && command != "-h" => command,
+        _ => {
+            println!("{}", USAGE);
+            return;
+        }
+    };
+    let result = Args::parse(args)
+        .map_err(Failure::Usage)
+        .and_then(|args| run(&command, &args));
+    match result {
+        Ok(()) => {}
+        Err(Failure::Usage(message)) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+        Err(Failure::Failed(err)) => {
+            eprint!("error: {}", err);
+            let mut source = err.source();
+            while let Some(err) = source {
+                eprint!(": {}", err);
+                source = err.source();
+            }
+            eprintln!();
+            process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,120 @@
+use log::info;
+use tokio::task;
+
+use crate::{data::PackageTag, download, progress::Phase, state::UpdaterState, Error, Reactor};
+
+// runs file system work of the reactor on the blocking thread pool, so that it
+// never stalls the executor
+async fn blocking<T, F>(reactor: &Reactor, f: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce(Reactor) -> Result<T, Error> + Send + 'static,
+{
+    let reactor = reactor.clone();
+    task::spawn_blocking(move || f(reactor))
+        .await
+        .map_err(Error::TaskError)?
+}
+
+impl Reactor {
+    pub async fn oneclick_async(&self) -> Result<(), Error> {
+        info!("starting checking update");
+        let state = blocking(self, |reactor| {
+            reactor.recover()?;
+            if reactor.self_update_if_available()? || reactor.check_health()? {
+                return Ok(None);
+            }
+            reactor.checking_state().map(Some)
+        })
+        .await?;
+        // nothing is left to do once another version has been started
+        let mut state = match state {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+        if self.skips_check(&state) {
+            info!("checked for updates recently, skipping");
+            return Ok(());
+        }
+        self.reporter.phase(Phase::Checking);
+        let checker = self.checker(&state);
+        let (fetched, manifest) = match checker.fetch_manifest_async().await {
+            Ok(fetched) => checker
+                .read_manifest(&fetched)
+                .map(|manifest| (fetched, manifest)),
+            Err(err) => Err(err),
+        }
+        .map_err(|err| self.check_failed(&state, err))?;
+        state.manifest = Some(fetched.clone());
+        blocking(self, move |reactor| {
+            UpdaterState::update(&reactor.state_dir, |saved| saved.manifest = Some(fetched))
+        })
+        .await?;
+        let checker = self.checker(&state);
+        let minimum_version = checker.minimum_version(&manifest);
+        let selected = self.select_update(checker.evaluate(&manifest)?);
+        let installed = match &selected {
+            Some((latest_version, _)) => self.update_async(latest_version).await.map(|()| true),
+            None => Ok(false),
+        };
+        self.require_supported(minimum_version, installed)?;
+        blocking(self, |reactor| reactor.record_check()).await?;
+        if let Some((latest_version, true)) = selected {
+            let version = latest_version.version;
+            if blocking(self, move |reactor| reactor.restart_into(&version)).await? {
+                return Ok(());
+            }
+        }
+        blocking(self, |reactor| reactor.self_update_if_available()).await?;
+        info!("finshed checking update");
+
+        Ok(())
+    }
+
+    pub async fn update_async(&self, package_tag: &PackageTag) -> Result<(), Error> {
+        let package_tag = package_tag.clone();
+        let (package_tag, download) = blocking(self, move |reactor| {
+            let download = reactor.prepare_download(&package_tag)?;
+            Ok((package_tag, download))
+        })
+        .await?;
+        self.reporter.phase(Phase::Downloading);
+        let (download, patched_hash) = blocking(self, move |reactor| {
+            let patched_hash = reactor.try_patch(&download)?;
+            Ok((download, patched_hash))
+        })
+        .await?;
+        let actual_hash = match patched_hash {
+            Some(actual_hash) => actual_hash,
+            None => {
+                download::download_file_async(
+                    &download.url,
+                    &download.path,
+                    download.expected_hash.algorithm(),
+                    &self.retry_policy,
+                    self.reporter.as_ref(),
+                )
+                .await?
+            }
+        };
+        blocking(self, move |reactor| {
+            reactor.install_download(&package_tag, &download, &actual_hash)
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{checker::UpdateChecker, data::VersionTag, Error};
+
+    #[tokio::test]
+    async fn test_check_update_async() {
+        // must fail with an error instead of panicking inside the runtime
+        let checker = UpdateChecker::new(VersionTag::new(1, 0, 0), "http://127.0.0.1:9/hello.json");
+        assert!(matches!(
+            checker.check_update_async().await,
+            Err(Error::UntrackedNetError { .. })
+        ));
+    }
+}
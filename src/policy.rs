@@ -0,0 +1,267 @@
+use std::{
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{data::VersionTag, state::unix_time, Error};
+
+// what the user allows the updater to install, e.g. from the settings of the
+// application. kept in the updater state, see `Reactor::set_update_policy`.
+// releases that are required to keep running ignore pins and deferrals, but not
+// skipped releases.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdatePolicy {
+    // only releases matching the requirement are installed, which may be older
+    // than the running version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pin: Option<VersionReq>,
+    // also releases reverted after failing their health check
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<VersionTag>,
+    // unix time before which nothing is installed
+    #[serde(
+        rename = "deferredUntil",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub deferred_until: Option<u64>,
+}
+
+impl UpdatePolicy {
+    pub fn allows(&self, version: &VersionTag) -> bool {
+        self.pin.as_ref().is_none_or(|pin| pin.matches(version)) && !self.is_skipped(version)
+    }
+
+    pub fn is_skipped(&self, version: &VersionTag) -> bool {
+        self.skipped.contains(version)
+    }
+
+    pub fn skip(&mut self, version: VersionTag) {
+        if !self.skipped.contains(&version) {
+            self.skipped.push(version);
+        }
+    }
+
+    pub fn defer_until(&mut self, time: SystemTime) {
+        let time = time
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        self.deferred_until = Some(time);
+    }
+
+    pub fn defer_for(&mut self, duration: Duration) {
+        self.deferred_until = Some(unix_time().saturating_add(duration.as_secs()));
+    }
+
+    pub fn is_deferred(&self) -> bool {
+        self.deferred_until
+            .is_some_and(|deferred_until| deferred_until > unix_time())
+    }
+}
+
+// a version requirement in the style of cargo: an exact version such as `1.4.2`
+// or `=1.4.2`, `~1.4` for any 1.4.x, or `^1.4` for any 1.x from 1.4 on. ranges
+// only compare major, minor and patch.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum VersionReq {
+    Exact(VersionTag),
+    Tilde(PartialVersion),
+    Caret(PartialVersion),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartialVersion {
+    major: u32,
+    minor: Option<u32>,
+    patch: Option<u32>,
+}
+
+impl PartialVersion {
+    fn lower(&self) -> (u32, u32, u32) {
+        (self.major, self.minor.unwrap_or(0), self.patch.unwrap_or(0))
+    }
+}
+
+impl VersionReq {
+    pub fn matches(&self, version: &VersionTag) -> bool {
+        let core = (version.major(), version.minor(), version.patch());
+        match self {
+            VersionReq::Exact(exact) => version == exact,
+            VersionReq::Tilde(partial) => {
+                let upper = match partial.minor {
+                    Some(minor) => minor.checked_add(1).map(|minor| (partial.major, minor, 0)),
+                    None => partial.major.checked_add(1).map(|major| (major, 0, 0)),
+                };
+                partial.lower() <= core && upper.is_none_or(|upper| core < upper)
+            }
+            VersionReq::Caret(partial) => {
+                // the leftmost non-zero part must not change
+                let upper = match (partial.major, partial.minor, partial.patch) {
+                    (0, Some(0), Some(patch)) => patch.checked_add(1).map(|patch| (0, 0, patch)),
+                    (0, Some(minor), _) => minor.checked_add(1).map(|minor| (0, minor, 0)),
+                    (major, _, _) => major.checked_add(1).map(|major| (major, 0, 0)),
+                };
+                // no upper bound when the part is already at its maximum
+                partial.lower() <= core && upper.is_none_or(|upper| core < upper)
+            }
+        }
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || Error::InvalidVersionRequirement(s.to_string());
+        let partial = |text: &str| {
+            let parts = text
+                .split('.')
+                .map(|part| part.parse::<u32>().map_err(|_| invalid()))
+                .collect::<Result<Vec<_>, _>>()?;
+            match parts[..] {
+                [major] => Ok(PartialVersion {
+                    major,
+                    minor: None,
+                    patch: None,
+                }),
+                [major, minor] => Ok(PartialVersion {
+                    major,
+                    minor: Some(minor),
+                    patch: None,
+                }),
+                [major, minor, patch] => Ok(PartialVersion {
+                    major,
+                    minor: Some(minor),
+                    patch: Some(patch),
+                }),
+                _ => Err(invalid()),
+            }
+        };
+        if let Some(text) = s.strip_prefix('~') {
+            return partial(text).map(VersionReq::Tilde);
+        }
+        if let Some(text) = s.strip_prefix('^') {
+            return partial(text).map(VersionReq::Caret);
+        }
+        let exact = s.strip_prefix('=').unwrap_or(s);
+        VersionTag::try_from(exact)
+            .map(VersionReq::Exact)
+            .map_err(|_| invalid())
+    }
+}
+
+impl TryFrom<String> for VersionReq {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<VersionReq> for String {
+    fn from(value: VersionReq) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for PartialVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.major)?;
+        if let Some(minor) = self.minor {
+            write!(f, ".{}", minor)?;
+        }
+        if let Some(patch) = self.patch {
+            write!(f, ".{}", patch)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for VersionReq {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VersionReq::Exact(version) => write!(f, "={}", version),
+            VersionReq::Tilde(partial) => write!(f, "~{}", partial),
+            VersionReq::Caret(partial) => write!(f, "^{}", partial),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_req() {
+        let matches = |req: &str, version: &str| {
+            req.parse::<VersionReq>()
+                .unwrap()
+                .matches(&version.parse().unwrap())
+        };
+        assert!(matches("1.4.2", "1.4.2"));
+        assert!(matches("=1.4.2", "1.4.2+build"));
+        assert!(!matches("1.4.2", "1.4.3"));
+
+        assert!(matches("~1.4", "1.4.0"));
+        assert!(matches("~1.4", "1.4.9"));
+        assert!(!matches("~1.4", "1.5.0"));
+        assert!(!matches("~1.4", "1.3.9"));
+        assert!(matches("~1.4.2", "1.4.3"));
+        assert!(!matches("~1.4.2", "1.4.1"));
+        assert!(matches("~1", "1.9.0"));
+        assert!(!matches("~1", "2.0.0"));
+
+        assert!(matches("^1.4", "1.9.0"));
+        assert!(!matches("^1.4", "2.0.0"));
+        assert!(!matches("^1.4", "1.3.0"));
+        assert!(matches("^0.4", "0.4.7"));
+        assert!(!matches("^0.4", "0.5.0"));
+        assert!(matches("^0.0.3", "0.0.3"));
+        assert!(!matches("^0.0.3", "0.0.4"));
+
+        assert!(matches("~1.4294967295", "1.4294967295.7"));
+        assert!(matches("~4294967295", "4294967295.3.0"));
+        assert!(!matches("~4294967295", "4294967294.9.0"));
+        assert!(matches("^4294967295", "4294967295.1.0"));
+        assert!(matches("^0.4294967295", "0.4294967295.2"));
+        assert!(matches("^0.0.4294967295", "0.0.4294967295"));
+
+        for invalid in ["", "~", "~1.x", "^1.2.3.4", "1.4", ">1.4"] {
+            assert!(invalid.parse::<VersionReq>().is_err(), "{}", invalid);
+        }
+        for req in ["=1.4.2-rc.1", "~1.4", "^0.0.3", "~1"] {
+            assert_eq!(req.parse::<VersionReq>().unwrap().to_string(), req);
+        }
+    }
+
+    #[test]
+    fn test_policy() {
+        let mut policy = UpdatePolicy::default();
+        assert!(policy.allows(&VersionTag::new(2, 0, 0)));
+        policy.pin = Some("~1.4".parse().unwrap());
+        policy.skip(VersionTag::new(1, 4, 3));
+        assert!(policy.allows(&VersionTag::new(1, 4, 2)));
+        assert!(!policy.allows(&VersionTag::new(1, 4, 3)));
+        assert!(!policy.allows(&VersionTag::new(1, 5, 0)));
+
+        assert!(!policy.is_deferred());
+        policy.defer_for(Duration::from_secs(3600));
+        assert!(policy.is_deferred());
+        policy.defer_until(SystemTime::now() - Duration::from_secs(1));
+        assert!(!policy.is_deferred());
+        policy.defer_for(Duration::MAX);
+        assert_eq!(policy.deferred_until, Some(u64::MAX));
+        assert!(policy.is_deferred());
+        policy.defer_until(SystemTime::now() - Duration::from_secs(1));
+
+        let yaml = serde_yaml::to_string(&policy).unwrap();
+        assert!(yaml.contains("pin: ~1.4"), "{}", yaml);
+        assert_eq!(serde_yaml::from_str::<UpdatePolicy>(&yaml).unwrap(), policy);
+    }
+}
@@ -0,0 +1,87 @@
+use std::{fmt::Debug, sync::Arc};
+
+#[cfg(feature = "progress-bar")]
+use std::sync::Mutex;
+
+#[cfg(feature = "progress-bar")]
+use indicatif::ProgressBar;
+
+// the steps a `Reactor` goes through while updating, in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Checking,
+    Downloading,
+    Verifying,
+    Extracting,
+    Installing,
+    Restarting,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DownloadProgress {
+    pub downloaded: u64,
+    // unknown when the publisher does not send a content length
+    pub total: Option<u64>,
+    // average rate of the current attempt
+    pub bytes_per_second: f64,
+}
+
+// receives the progress of an update. called from whichever thread runs the
+// update, so implementations must not block for long.
+pub trait ProgressReporter: Send + Sync {
+    fn phase(&self, phase: Phase);
+
+    fn download(&self, _progress: DownloadProgress) {}
+}
+
+impl Debug for dyn ProgressReporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgressReporter")
+    }
+}
+
+// reports nothing, for services without a terminal
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SilentReporter;
+
+impl ProgressReporter for SilentReporter {
+    fn phase(&self, _phase: Phase) {}
+}
+
+// draws a progress bar on stderr while downloading
+#[cfg(feature = "progress-bar")]
+#[derive(Debug, Default)]
+pub struct ProgressBarReporter {
+    bar: Mutex<Option<ProgressBar>>,
+}
+
+#[cfg(feature = "progress-bar")]
+impl ProgressReporter for ProgressBarReporter {
+    fn phase(&self, phase: Phase) {
+        let mut bar = self.bar.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(bar) = bar.take() {
+            bar.finish_with_message("finish downloading");
+        }
+        if phase == Phase::Downloading {
+            *bar = Some(ProgressBar::new(0));
+        }
+    }
+
+    fn download(&self, progress: DownloadProgress) {
+        let bar = self.bar.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(bar) = bar.as_ref() {
+            bar.set_length(progress.total.unwrap_or(0));
+            bar.set_position(progress.downloaded);
+        }
+    }
+}
+
+#[cfg(feature = "progress-bar")]
+pub fn default_reporter() -> Arc<dyn ProgressReporter> {
+    Arc::new(ProgressBarReporter::default())
+}
+
+#[cfg(not(feature = "progress-bar"))]
+pub fn default_reporter() -> Arc<dyn ProgressReporter> {
+    Arc::new(SilentReporter)
+}
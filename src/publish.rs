@@ -0,0 +1,655 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{self, AtomicUsize},
+};
+
+use ed25519_dalek::SigningKey;
+use log::{info, warn};
+
+use crate::{
+    archive::{self, ArchiveFormat},
+    data::{Artifact, Manifest, PackageTag, VersionTag},
+    files::FileManifest,
+    hash::{self, HashAlgorithm, PackageHash},
+    signature::ManifestSignature,
+    state::unix_time,
+    utils, Error, TrustedKey,
+};
+
+// an archive published as a release on a channel. without `target`, `arch` and
+// `os` it is installed on every target.
+#[derive(Debug, Clone)]
+pub struct Release {
+    pub channel: String,
+    pub version: String,
+    pub archive: PathBuf,
+    pub download_url: String,
+    pub target: Option<String>,
+    pub arch: Option<String>,
+    pub os: Option<String>,
+    // taken from the name or the content of the archive when omitted
+    pub format: Option<ArchiveFormat>,
+    pub hash_algorithm: String,
+    // today when omitted
+    pub date: Option<String>,
+    pub changelog: Option<String>,
+    // older versions have to update before they can run
+    pub mandatory: bool,
+    // the percentage of clients offered the release, all when omitted
+    pub rollout: Option<u8>,
+}
+
+impl Release {
+    pub fn new(
+        channel: impl Into<String>,
+        version: impl Into<String>,
+        archive: impl Into<PathBuf>,
+        download_url: impl Into<String>,
+    ) -> Self {
+        Release {
+            channel: channel.into(),
+            version: version.into(),
+            archive: archive.into(),
+            download_url: download_url.into(),
+            target: None,
+            arch: None,
+            os: None,
+            format: None,
+            hash_algorithm: HashAlgorithm::Sha256.name().to_string(),
+            date: None,
+            changelog: None,
+            mandatory: false,
+            rollout: None,
+        }
+    }
+
+    fn is_universal(&self) -> bool {
+        self.target.is_none() && self.arch.is_none() && self.os.is_none()
+    }
+}
+
+// something wrong with a manifest or one of the files it references
+#[derive(Debug)]
+pub struct Problem {
+    pub location: String,
+    pub error: Error,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.error)
+    }
+}
+
+// packs a build directory, or a single executable for `raw`, into a release
+// archive. the format is taken from the name of `dest` when omitted.
+pub fn package(
+    build_dir: impl AsRef<Path>,
+    dest: impl AsRef<Path>,
+    format: Option<ArchiveFormat>,
+) -> Result<ArchiveFormat, Error> {
+    let dest = dest.as_ref();
+    let format = match format {
+        Some(format) => format,
+        None => dest
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .and_then(ArchiveFormat::from_name)
+            .ok_or_else(|| Error::UnsupportedArchiveFormat(dest.display().to_string()))?,
+    };
+    archive::create(format, build_dir, dest)?;
+    info!("packed {} archive {:?}", format, dest);
+    Ok(format)
+}
+
+// the hash of a file as written into a manifest, e.g. `sha256:2cf24d...`
+pub fn hash(path: impl AsRef<Path>, algorithm: &str) -> Result<String, Error> {
+    let algorithm = HashAlgorithm::try_from(algorithm)?;
+    Ok(hash::hash_file(path.as_ref(), algorithm)?.to_string())
+}
+
+// the files of an archive and their digests, found by extracting it into a
+// temporary directory. raw artifacts are named after the application on the
+// client and are not listed.
+fn package_files(archive: &Path, format: ArchiveFormat) -> Result<BTreeMap<String, String>, Error> {
+    static EXTRACTIONS: AtomicUsize = AtomicUsize::new(0);
+    if format == ArchiveFormat::Raw {
+        return Ok(BTreeMap::new());
+    }
+    let dir = env::temp_dir().join(format!(
+        "reduction-react-{}-{}",
+        process::id(),
+        EXTRACTIONS.fetch_add(1, atomic::Ordering::Relaxed)
+    ));
+    let files =
+        archive::extract(format, archive, &dir, "").and_then(|_| FileManifest::from_dir(&dir));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).map_err(|err| Error::CommonFileError {
+            message: format!("failed to remove temporary directory `{:?}`", &dir),
+            source: err,
+        })?;
+    }
+    Ok(files?.files)
+}
+
+// adds `release` to the manifest at `manifest_path`, which is created if it does
+// not exist yet. a new version is added to the history of the channel, while the
+// same version collects the artifacts of several targets.
+pub fn add_release(manifest_path: impl AsRef<Path>, release: &Release) -> Result<(), Error> {
+    let manifest_path = manifest_path.as_ref();
+    let mut manifest = if manifest_path.exists() {
+        Manifest::from_yaml(&read_to_string(manifest_path)?)?
+    } else {
+        Manifest::default()
+    };
+    let version = VersionTag::try_from(release.version.as_str())?;
+    if let Some(date) = release.date.as_deref().filter(|date| !is_date(date)) {
+        return Err(Error::InvalidDate(date.to_string()));
+    }
+    let hash = hash(&release.archive, &release.hash_algorithm)?;
+    let format = match release.format {
+        Some(format) => format,
+        None => release
+            .archive
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .and_then(ArchiveFormat::from_name)
+            .map_or_else(|| ArchiveFormat::detect(&release.archive), Ok)?,
+    };
+    let files = package_files(&release.archive, format)?;
+
+    let releases = manifest
+        .channels
+        .entry(release.channel.clone())
+        .or_default();
+    let index = match releases
+        .iter()
+        .position(|published| published.version <= version)
+    {
+        Some(index) if releases[index].version == version => index,
+        Some(index) => {
+            releases.insert(index, PackageTag::with_artifacts(version, vec![]));
+            index
+        }
+        None => {
+            releases.push(PackageTag::with_artifacts(version, vec![]));
+            releases.len() - 1
+        }
+    };
+    let package_tag = &mut releases[index];
+    if let Some(date) = &release.date {
+        package_tag.date = Some(date.clone());
+    }
+    package_tag.date.get_or_insert_with(today);
+    if let Some(changelog) = &release.changelog {
+        package_tag.changelog = Some(changelog.clone());
+    }
+    package_tag.mandatory |= release.mandatory;
+    if let Some(percentage) = release.rollout {
+        package_tag.rollout = Some(percentage).filter(|percentage| *percentage < 100);
+    }
+    if release.is_universal() {
+        package_tag.hash = Some(hash);
+        package_tag.download_url = Some(release.download_url.clone());
+        package_tag.format = Some(format);
+        package_tag.patches.clear();
+        package_tag.files = files;
+    } else {
+        let artifact = Artifact {
+            target: release.target.clone(),
+            arch: release.arch.clone(),
+            os: release.os.clone(),
+            hash,
+            download_url: release.download_url.clone(),
+            format: Some(format),
+            patches: vec![],
+            files,
+        };
+        package_tag.artifacts.retain(|published| {
+            (&published.target, &published.arch, &published.os)
+                != (&artifact.target, &artifact.arch, &artifact.os)
+        });
+        package_tag.artifacts.push(artifact);
+    }
+
+    write(manifest_path, manifest.to_yaml()?.as_bytes())?;
+    info!(
+        "published {} on channel `{}` in {:?}",
+        release.version, release.channel, manifest_path
+    );
+    if signature_path(manifest_path).exists() {
+        warn!("the manifest changed, its signature has to be renewed");
+    }
+    Ok(())
+}
+
+// makes every client below `version` update before it can run
+pub fn set_minimum_version(manifest_path: impl AsRef<Path>, version: &str) -> Result<(), Error> {
+    let manifest_path = manifest_path.as_ref();
+    let mut manifest = Manifest::from_yaml(&read_to_string(manifest_path)?)?;
+    manifest.minimum_supported_version = Some(VersionTag::try_from(version)?);
+    write(manifest_path, manifest.to_yaml()?.as_bytes())?;
+    info!(
+        "{} is the minimum supported version in {:?}",
+        version, manifest_path
+    );
+    Ok(())
+}
+
+// offers a published release to `percentage` of the clients. raising it keeps
+// the release with everyone who already got it, 100 ends the staged rollout.
+pub fn set_rollout(
+    manifest_path: impl AsRef<Path>,
+    channel: &str,
+    version: &str,
+    percentage: u8,
+) -> Result<(), Error> {
+    let manifest_path = manifest_path.as_ref();
+    let mut manifest = Manifest::from_yaml(&read_to_string(manifest_path)?)?;
+    let version = VersionTag::try_from(version)?;
+    let package_tag = manifest
+        .channels
+        .get_mut(channel)
+        .and_then(|releases| {
+            releases
+                .iter_mut()
+                .find(|package_tag| package_tag.version == version)
+        })
+        .ok_or_else(|| Error::UnknownRelease {
+            channel: channel.to_string(),
+            version: version.to_string(),
+        })?;
+    package_tag.rollout = Some(percentage).filter(|percentage| *percentage < 100);
+    write(manifest_path, manifest.to_yaml()?.as_bytes())?;
+    info!(
+        "rolled out {} on channel `{}` to {}% of clients",
+        version, channel, percentage
+    );
+    Ok(())
+}
+
+// where the signature of the manifest at `manifest_path` is kept, next to it
+pub fn signature_path(manifest_path: impl AsRef<Path>) -> PathBuf {
+    let manifest_path = manifest_path.as_ref();
+    let mut file_name = manifest_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".sig");
+    manifest_path.with_file_name(file_name)
+}
+
+// the ed25519 secret key stored as hex in `key_path`, as created by e.g.
+// `openssl rand -hex 32`
+fn load_signing_key(key_path: &Path) -> Result<SigningKey, Error> {
+    let bytes: [u8; 32] = hex::decode(read_to_string(key_path)?.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| Error::InvalidSigningKey(key_path.display().to_string()))?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+// the hex encoded public key clients pass to `TrustedKey::from_hex`
+pub fn public_key(key_path: impl AsRef<Path>) -> Result<String, Error> {
+    let signing_key = load_signing_key(key_path.as_ref())?;
+    Ok(hex::encode(signing_key.verifying_key().as_bytes()))
+}
+
+// writes the detached signature of the manifest to `signature_path`
+pub fn sign(
+    manifest_path: impl AsRef<Path>,
+    key_id: &str,
+    key_path: impl AsRef<Path>,
+) -> Result<PathBuf, Error> {
+    let manifest_path = manifest_path.as_ref();
+    let signing_key = load_signing_key(key_path.as_ref())?;
+    let manifest = fs::read(manifest_path).map_err(|err| Error::CommonFileError {
+        message: format!("failed to read manifest `{:?}`", manifest_path),
+        source: err,
+    })?;
+    let signature = ManifestSignature::sign(key_id, &signing_key, &manifest);
+    let path = signature_path(manifest_path);
+    write(&path, serde_yaml::to_string(&signature)?.as_bytes())?;
+    info!("signed {:?} with key `{}`", manifest_path, key_id);
+    Ok(path)
+}
+
+// checks the manifest at `manifest_path` against the archives and patches it
+// references, which are looked up in `releases_dir`. a download url is taken
+// relative to `base_url`, or by its last path segment without one. once any key
+// is trusted, the signature of the manifest is checked as well.
+pub fn validate(
+    manifest_path: impl AsRef<Path>,
+    releases_dir: impl AsRef<Path>,
+    base_url: Option<&str>,
+    trusted_keys: &[TrustedKey],
+) -> Result<Vec<Problem>, Error> {
+    let manifest_path = manifest_path.as_ref();
+    let releases_dir = releases_dir.as_ref();
+    let text = read_to_string(manifest_path)?;
+    let mut problems = vec![];
+    if !trusted_keys.is_empty() {
+        if let Err(error) = check_signature(manifest_path, &text, trusted_keys) {
+            problems.push(Problem {
+                location: "signature".to_string(),
+                error,
+            });
+        }
+    }
+
+    let manifest = Manifest::from_yaml(&text)?;
+    if let Some(minimum_version) = &manifest.minimum_supported_version {
+        for (channel, releases) in manifest.channels.iter() {
+            match releases.first() {
+                Some(latest) if latest.version < *minimum_version => problems.push(Problem {
+                    location: format!("channel `{}`", channel),
+                    error: Error::InvalidManifest(format!(
+                        "latest release {} is below the minimum supported version {}",
+                        latest.version, minimum_version
+                    )),
+                }),
+                _ => {}
+            }
+        }
+    }
+    let releases = manifest.channels.iter().flat_map(|(channel, releases)| {
+        releases
+            .iter()
+            .map(move |package_tag| (channel, package_tag))
+    });
+    for (channel, package_tag) in releases {
+        let mut files = vec![];
+        if let (Some(hash), Some(download_url)) = (&package_tag.hash, &package_tag.download_url) {
+            files.push((hash, download_url, package_tag.format));
+        }
+        for artifact in package_tag.artifacts.iter() {
+            files.push((&artifact.hash, &artifact.download_url, artifact.format));
+        }
+        if package_tag
+            .rollout
+            .is_some_and(|percentage| percentage > 100)
+        {
+            problems.push(Problem {
+                location: format!("channel `{}`", channel),
+                error: Error::InvalidManifest(format!(
+                    "release {} is rolled out to more than 100% of clients",
+                    package_tag.version
+                )),
+            });
+        }
+        if files.is_empty() {
+            problems.push(Problem {
+                location: format!("channel `{}`", channel),
+                error: Error::InvalidManifest(format!(
+                    "release {} has no artifacts",
+                    package_tag.version
+                )),
+            });
+        }
+        let patches = package_tag.patches.iter().chain(
+            package_tag
+                .artifacts
+                .iter()
+                .flat_map(|artifact| &artifact.patches),
+        );
+        for patch in patches {
+            files.push((&patch.hash, &patch.download_url, None));
+        }
+
+        for (hash, download_url, format) in files {
+            let result = local_path(releases_dir, base_url, download_url)
+                .and_then(|path| check_file(&path, hash, format));
+            if let Err(error) = result {
+                problems.push(Problem {
+                    location: format!(
+                        "channel `{}`, release {}, `{}`",
+                        channel, package_tag.version, download_url
+                    ),
+                    error,
+                });
+            }
+        }
+    }
+    Ok(problems)
+}
+
+fn check_signature(
+    manifest_path: &Path,
+    manifest: &str,
+    trusted_keys: &[TrustedKey],
+) -> Result<(), Error> {
+    let path = signature_path(manifest_path);
+    if !path.exists() {
+        return Err(Error::MissingSignature);
+    }
+    let signature = serde_yaml::from_str::<ManifestSignature>(&read_to_string(&path)?)?;
+    signature.verify(trusted_keys, manifest.as_bytes())
+}
+
+fn local_path(
+    releases_dir: &Path,
+    base_url: Option<&str>,
+    download_url: &str,
+) -> Result<PathBuf, Error> {
+    let relative_path = match base_url {
+        Some(base_url) => download_url
+            .strip_prefix(base_url.trim_end_matches('/'))
+            .map(|path| path.trim_start_matches('/')),
+        None => download_url.rsplit('/').next(),
+    };
+    relative_path
+        .filter(|path| {
+            path.split('/')
+                .all(|component| !matches!(component, "" | "." | ".."))
+        })
+        .map(|path| releases_dir.join(path.split('/').collect::<PathBuf>()))
+        .ok_or_else(|| Error::InvalidManifest(format!("`{}` is not a release file", download_url)))
+}
+
+fn check_file(path: &Path, hash: &str, format: Option<ArchiveFormat>) -> Result<(), Error> {
+    let expected_hash = PackageHash::try_from(hash)?;
+    expected_hash.verify(&hash::hash_file(path, expected_hash.algorithm())?)?;
+    // any file passes for a raw executable
+    if let Some(format) = format.filter(|format| *format != ArchiveFormat::Raw) {
+        if ArchiveFormat::detect(path)? != format {
+            return Err(Error::InvalidManifest(format!(
+                "`{:?}` is not a {} archive",
+                path, format
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn today() -> String {
+    date(unix_time())
+}
+
+// the utc date of a unix timestamp as `yyyy-mm-dd`
+fn date(unix_time: u64) -> String {
+    let (year, month, day) = utils::civil_date(unix_time);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// whether `text` is a date written as `yyyy-mm-dd`
+fn is_date(text: &str) -> bool {
+    let well_formed = text.len() == 10
+        && text.bytes().enumerate().all(|(i, byte)| match i {
+            4 | 7 => byte == b'-',
+            _ => byte.is_ascii_digit(),
+        });
+    if !well_formed {
+        return false;
+    }
+    let number = |range: std::ops::Range<usize>| text[range].parse::<u32>().unwrap_or_default();
+    let (year, month, day) = (number(0..4), number(5..7), number(8..10));
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days_in_month).contains(&day)
+}
+
+fn read_to_string(path: &Path) -> Result<String, Error> {
+    fs::read_to_string(path).map_err(|err| Error::CommonFileError {
+        message: format!("failed to read `{:?}`", path),
+        source: err,
+    })
+}
+
+fn write(path: &Path, content: &[u8]) -> Result<(), Error> {
+    fs::write(path, content).map_err(|err| Error::CommonFileError {
+        message: format!("failed to write `{:?}`", path),
+        source: err,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn test_publish() {
+        let dir = tempfile::tempdir().unwrap();
+        let build_dir = dir.path().join("build");
+        fs::create_dir_all(build_dir.join("lib")).unwrap();
+        fs::write(build_dir.join("hello-1.2.0"), "v1.2.0").unwrap();
+        fs::write(build_dir.join("lib/data.txt"), "data").unwrap();
+        let releases_dir = dir.path().join("releases");
+        fs::create_dir_all(&releases_dir).unwrap();
+        let archive = releases_dir.join("hello-1.2.0.zip");
+        assert_eq!(
+            package(&build_dir, &archive, None).unwrap(),
+            ArchiveFormat::Zip
+        );
+        let exe = releases_dir.join("hello-1.2.0");
+        fs::copy(build_dir.join("hello-1.2.0"), &exe).unwrap();
+
+        let manifest_path = releases_dir.join("hello.yaml");
+        let mut linux = Release::new(
+            "stable",
+            "1.2.0",
+            &archive,
+            "http://127.0.0.1/hello-1.2.0.zip",
+        );
+        linux.os = Some("linux".to_string());
+        add_release(&manifest_path, &linux).unwrap();
+        let mut windows = Release::new("stable", "1.2.0", &exe, "http://127.0.0.1/hello-1.2.0");
+        windows.target = Some("x86_64-pc-windows-msvc".to_string());
+        add_release(&manifest_path, &windows).unwrap();
+        add_release(&manifest_path, &linux).unwrap();
+
+        let manifest = Manifest::from_yaml(&read_to_string(&manifest_path).unwrap()).unwrap();
+        let package_tag = manifest.channel("stable").unwrap();
+        assert_eq!(package_tag.version, VersionTag::new(1, 2, 0));
+        assert_eq!(package_tag.artifacts.len(), 2);
+        assert_eq!(package_tag.artifacts[0].format, Some(ArchiveFormat::Raw));
+        assert_eq!(
+            package_tag.artifacts[1].hash,
+            hash(&archive, "sha256").unwrap()
+        );
+        assert!(package_tag.artifacts[0].files.is_empty());
+        assert_eq!(
+            package_tag.artifacts[1].files.keys().collect::<Vec<_>>(),
+            vec!["hello-1.2.0", "lib/data.txt"]
+        );
+        assert_eq!(
+            package_tag.artifacts[1].files["lib/data.txt"],
+            crate::files::digest(&build_dir.join("lib/data.txt")).unwrap()
+        );
+
+        let key_path = dir.path().join("key");
+        fs::write(&key_path, hex::encode([7; 32])).unwrap();
+        sign(&manifest_path, "2022", &key_path).unwrap();
+        let trusted_key = TrustedKey::from_hex("2022", &public_key(&key_path).unwrap()).unwrap();
+        let problems = validate(
+            &manifest_path,
+            &releases_dir,
+            Some("http://127.0.0.1"),
+            std::slice::from_ref(&trusted_key),
+        )
+        .unwrap();
+        assert!(problems.is_empty(), "{:?}", problems);
+
+        // a new version is added to the history of the channel
+        fs::write(&exe, "v1.3.0").unwrap();
+        let mut release = Release::new("stable", "1.3.0", &exe, "http://127.0.0.1/hello-1.2.0");
+        release.date = Some("2022-5-1".to_string());
+        assert!(matches!(
+            add_release(&manifest_path, &release),
+            Err(Error::InvalidDate(_))
+        ));
+        release.date = Some("2022-05-01".to_string());
+        release.changelog = Some("fixes".to_string());
+        release.mandatory = true;
+        release.rollout = Some(5);
+        add_release(&manifest_path, &release).unwrap();
+        assert!(matches!(
+            set_rollout(&manifest_path, "stable", "1.1.0", 20),
+            Err(Error::UnknownRelease { .. })
+        ));
+        set_rollout(&manifest_path, "stable", "1.3.0", 20).unwrap();
+        set_minimum_version(&manifest_path, "1.4.0").unwrap();
+        let manifest = Manifest::from_yaml(&read_to_string(&manifest_path).unwrap()).unwrap();
+        let releases = manifest.releases("stable").unwrap();
+        assert_eq!(releases[0].version, VersionTag::new(1, 3, 0));
+        assert_eq!(releases[0].date.as_deref(), Some("2022-05-01"));
+        assert_eq!(releases[0].changelog.as_deref(), Some("fixes"));
+        assert!(releases[0].mandatory);
+        assert_eq!(releases[0].rollout, Some(20));
+        assert!(!releases[1].mandatory);
+        assert_eq!(
+            manifest.minimum_supported_version,
+            Some(VersionTag::new(1, 4, 0))
+        );
+        assert_eq!(releases[1].version, VersionTag::new(1, 2, 0));
+        assert_eq!(releases[1].artifacts.len(), 2);
+        assert!(releases[1].date.is_some());
+
+        fs::write(&exe, "tampered").unwrap();
+        let problems = validate(&manifest_path, &releases_dir, None, &[trusted_key]).unwrap();
+        assert!(matches!(problems[0].error, Error::InvalidSignature(_)));
+        assert!(matches!(problems[1].error, Error::InvalidManifest(_)));
+        assert!(matches!(problems[2].error, Error::HashMismatch { .. }));
+        assert!(matches!(problems[3].error, Error::HashMismatch { .. }));
+        assert_eq!(problems.len(), 4);
+    }
+
+    #[test]
+    fn test_date() {
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(951_825_599), "2000-02-29");
+        assert_eq!(date(1_651_363_200), "2022-05-01");
+        assert_eq!(date(4_107_542_400), "2100-03-01");
+        assert!(is_date("2022-05-01"));
+        assert!(is_date("2000-02-29"));
+        assert!(!is_date("2100-02-29"));
+        assert!(!is_date("2022-13-01"));
+        assert!(!is_date("2022-04-31"));
+        assert!(!is_date("2022-5-1"));
+        assert!(!is_date("2022-+5-01"));
+        assert!(!is_date("yesterday"));
+    }
+
+    #[test]
+    fn test_local_path() {
+        let dir = Path::new("releases");
+        assert_eq!(
+            local_path(dir, None, "http://host/a/hello.zip").unwrap(),
+            dir.join("hello.zip")
+        );
+        assert_eq!(
+            local_path(dir, Some("http://host/"), "http://host/a/hello.zip").unwrap(),
+            dir.join("a").join("hello.zip")
+        );
+        assert!(local_path(dir, Some("http://host"), "http://other/hello.zip").is_err());
+        assert!(local_path(dir, Some("http://host"), "http://host/../hello.zip").is_err());
+    }
+}
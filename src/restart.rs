@@ -0,0 +1,48 @@
+use std::{fmt::Debug, path::Path};
+
+use crate::Error;
+
+// starts the executable of another version. the updater stops once it called
+// `restart`, so a restarter that returns leaves everything else to the started
+// version, e.g. in tests.
+pub trait Restarter: Send + Sync {
+    fn restart(&self, executable: &Path) -> Result<(), Error>;
+}
+
+impl Debug for dyn Restarter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Restarter")
+    }
+}
+
+// replaces the running process with the started executable
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessRestarter;
+
+impl Restarter for ProcessRestarter {
+    #[cfg(unix)]
+    fn restart(&self, executable: &Path) -> Result<(), Error> {
+        use std::os::unix::prelude::CommandExt;
+        // only returns if the executable could not be started
+        let err = std::process::Command::new(executable).exec();
+        Err(Error::CommonFileError {
+            message: format!("failed to start `{:?}`", executable),
+            source: err,
+        })
+    }
+
+    #[cfg(windows)]
+    fn restart(&self, executable: &Path) -> Result<(), Error> {
+        std::process::Command::new("cmd")
+            .arg("/C")
+            .arg("start")
+            .arg("")
+            .arg(executable)
+            .output()
+            .map_err(|err| Error::CommonFileError {
+                message: format!("failed to start `{:?}`", executable),
+                source: err,
+            })?;
+        std::process::exit(0);
+    }
+}
@@ -0,0 +1,540 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use log::{info, trace, warn};
+
+use crate::Error;
+
+// misbehavior of the release server, to test how clients cope with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    // answers with `500 Internal Server Error`
+    ServerError,
+    // announces the whole body, but closes the connection after this many bytes
+    Truncate(u64),
+    // waits before answering
+    Delay(Duration),
+    // sends the body with at most this many bytes per second
+    Throttle(u64),
+}
+
+// a request the server received, for tests to check what a client asked for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub path: String,
+    pub range: Option<String>,
+    pub if_none_match: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    // each request takes the first of these
+    next_faults: VecDeque<Fault>,
+    // each request for the path takes the first fault queued for it
+    path_faults: Vec<(String, Fault)>,
+    // applied to every request
+    faults: Vec<Fault>,
+    requests: Vec<Request>,
+}
+
+// serves the files below a releases directory over plain http, with support for
+// `Range` and `If-None-Match` requests. it runs on a background thread until dropped.
+pub struct ReleaseServer {
+    addr: SocketAddr,
+    shared: Arc<Mutex<Shared>>,
+    stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl ReleaseServer {
+    // binds to `addr`. use port 0 to pick a free port, e.g. `127.0.0.1:0`.
+    pub fn bind(root: impl Into<PathBuf>, addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        let root = root.into();
+        let listener = TcpListener::bind(addr).map_err(|err| Error::CommonFileError {
+            message: "failed to bind release server".to_string(),
+            source: err,
+        })?;
+        let addr = listener
+            .local_addr()
+            .map_err(|err| Error::CommonFileError {
+                message: "failed to bind release server".to_string(),
+                source: err,
+            })?;
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let (shared, stopped) = (shared.clone(), stopped.clone());
+            thread::spawn(move || accept(listener, root, shared, stopped))
+        };
+        info!("serving releases at http://{}", addr);
+        Ok(ReleaseServer {
+            addr,
+            shared,
+            stopped,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    // the url of a file relative to the releases directory
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}/{}", self.addr, path.trim_start_matches('/'))
+    }
+
+    // queued faults are used up one per request, in order
+    pub fn fault_next(&self, fault: Fault) {
+        self.lock().next_faults.push_back(fault);
+    }
+
+    // like `fault_next`, but only counts requests for `path`, e.g.
+    // `hello-2.0.0.zip`
+    pub fn fault_path(&self, path: &str, fault: Fault) {
+        let path = format!("/{}", path.trim_start_matches('/'));
+        self.lock().path_faults.push((path, fault));
+    }
+
+    pub fn fault_always(&self, fault: Fault) {
+        self.lock().faults.push(fault);
+    }
+
+    pub fn clear_faults(&self) {
+        let mut shared = self.lock();
+        shared.next_faults.clear();
+        shared.path_faults.clear();
+        shared.faults.clear();
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.lock().requests.clone()
+    }
+
+    // serves until the process exits
+    pub fn join(mut self) {
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Drop for ReleaseServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.handle.take() {
+            // wakes up the blocking `accept`
+            TcpStream::connect(self.addr).ok();
+            handle.join().ok();
+        }
+    }
+}
+
+fn accept(
+    listener: TcpListener,
+    root: PathBuf,
+    shared: Arc<Mutex<Shared>>,
+    stopped: Arc<AtomicBool>,
+) {
+    for stream in listener.incoming() {
+        if stopped.load(Ordering::SeqCst) {
+            break;
+        }
+        match stream {
+            Ok(stream) => {
+                let (root, shared) = (root.clone(), shared.clone());
+                thread::spawn(move || {
+                    if let Err(err) = handle(stream, &root, &shared) {
+                        trace!("connection closed: {}", err);
+                    }
+                });
+            }
+            Err(err) => warn!("failed to accept connection: {}", err),
+        }
+    }
+}
+
+fn handle(mut stream: TcpStream, root: &Path, shared: &Mutex<Shared>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut range = None;
+    let mut if_range = None;
+    let mut if_none_match = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            match name.trim().to_ascii_lowercase().as_str() {
+                "range" => range = Some(value.trim().to_string()),
+                "if-range" => if_range = Some(value.trim().to_string()),
+                "if-none-match" => if_none_match = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+    }
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method, target),
+        _ => return respond(&mut stream, "400 Bad Request", &[], b""),
+    };
+    let path = target.split('?').next().unwrap_or_default().to_string();
+    trace!("{} {} range {:?}", method, path, range);
+
+    let faults = {
+        let mut shared = shared.lock().unwrap_or_else(|err| err.into_inner());
+        shared.requests.push(Request {
+            path: path.clone(),
+            range: range.clone(),
+            if_none_match: if_none_match.clone(),
+        });
+        let mut faults = shared.faults.clone();
+        faults.extend(shared.next_faults.pop_front());
+        if let Some(index) = shared
+            .path_faults
+            .iter()
+            .position(|(fault_path, _)| *fault_path == path)
+        {
+            faults.push(shared.path_faults.remove(index).1);
+        }
+        faults
+    };
+    let mut truncate = None;
+    let mut throttle = None;
+    for fault in faults {
+        match fault {
+            Fault::ServerError => {
+                return respond(&mut stream, "500 Internal Server Error", &[], b"")
+            }
+            Fault::Truncate(len) => truncate = Some(len),
+            Fault::Delay(delay) => thread::sleep(delay),
+            Fault::Throttle(bytes_per_second) => throttle = Some(bytes_per_second.max(1)),
+        }
+    }
+
+    if method != "GET" && method != "HEAD" {
+        return respond(&mut stream, "405 Method Not Allowed", &[], b"");
+    }
+    let mut file = match open(root, &path) {
+        Some(file) => file,
+        None => return respond(&mut stream, "404 Not Found", &[], b""),
+    };
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return respond(&mut stream, "404 Not Found", &[], b"");
+    }
+    let len = metadata.len();
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .map(|mtime| mtime.as_nanos())
+        .unwrap_or_default();
+    let etag = format!("\"{:x}-{:x}\"", len, mtime);
+
+    if if_none_match.is_some_and(|if_none_match| if_none_match == etag || if_none_match == "*") {
+        return write_head(&mut stream, "304 Not Modified", &[("ETag", etag)])
+            .and_then(|_| stream.flush());
+    }
+    // a stale `If-Range` asks for the whole, changed file
+    let range = range.filter(|_| if_range.as_ref().is_none_or(|if_range| *if_range == etag));
+    let mut headers = vec![
+        ("Accept-Ranges", "bytes".to_string()),
+        ("ETag", etag),
+        ("Content-Type", "application/octet-stream".to_string()),
+    ];
+    let (status, start, end) = match range.map(|range| parse_range(&range, len)) {
+        None => ("200 OK", 0, len),
+        Some(Some((start, end))) => {
+            headers.push((
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, end - 1, len),
+            ));
+            ("206 Partial Content", start, end)
+        }
+        Some(None) => {
+            headers.push(("Content-Range", format!("bytes */{}", len)));
+            return respond(&mut stream, "416 Range Not Satisfiable", &headers, b"");
+        }
+    };
+    headers.push(("Content-Length", (end - start).to_string()));
+    write_head(&mut stream, status, &headers)?;
+    if method == "HEAD" {
+        return Ok(());
+    }
+
+    file.seek(SeekFrom::Start(start))?;
+    let mut body = file.take(end - start);
+    let mut remaining = truncate.unwrap_or(u64::MAX);
+    let chunk_len = throttle.map_or(64 * 1024, |bytes_per_second| {
+        (bytes_per_second / 10).clamp(1, 64 * 1024)
+    });
+    let mut buf = vec![0; chunk_len as usize];
+    while remaining > 0 {
+        let max = buf
+            .len()
+            .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        let n = body.read(&mut buf[..max])?;
+        if n == 0 {
+            break;
+        }
+        stream.write_all(&buf[..n])?;
+        remaining -= n as u64;
+        if let Some(bytes_per_second) = throttle {
+            thread::sleep(Duration::from_secs_f64(n as f64 / bytes_per_second as f64));
+        }
+    }
+    stream.flush()
+}
+
+// opens the file a request path points to. symlinks are followed as long as
+// they end up inside `root`.
+fn open(root: &Path, path: &str) -> Option<fs::File> {
+    let resolved = resolve(root, path)?.canonicalize().ok()?;
+    if !resolved.starts_with(root.canonicalize().ok()?) {
+        warn!("refused {}, which leads outside of {:?}", path, root);
+        return None;
+    }
+    fs::File::open(resolved).ok()
+}
+
+// the path below `root` a percent-encoded request path names, if it stays
+// inside `root`
+fn resolve(root: &Path, path: &str) -> Option<PathBuf> {
+    let mut resolved = root.to_path_buf();
+    for component in path.split('/').filter(|component| !component.is_empty()) {
+        let component = percent_decode(component)?;
+        if matches!(component.as_str(), "." | "..") || component.contains(['/', '\\', '\0']) {
+            return None;
+        }
+        resolved.push(component);
+    }
+    Some(resolved)
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = text.bytes();
+    let mut decoded = vec![];
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let high = char::from(bytes.next()?).to_digit(16)?;
+            let low = char::from(bytes.next()?).to_digit(16)?;
+            decoded.push((high * 16 + low) as u8);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+// the byte range `start..end` a `Range` header asks for, or `None` if it cannot
+// be satisfied. only a single range is supported.
+fn parse_range(range: &str, len: u64) -> Option<(u64, u64)> {
+    let (start, end) = range.strip_prefix("bytes=")?.trim().split_once('-')?;
+    let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) => (start, end.saturating_add(1).min(len)),
+        (Ok(start), Err(_)) if end.is_empty() => (start, len),
+        // the last `end` bytes
+        (Err(_), Ok(suffix)) if start.is_empty() && suffix > 0 => (len.saturating_sub(suffix), len),
+        _ => return None,
+    };
+    Some((start, end)).filter(|(start, end)| start < end)
+}
+
+fn write_head(stream: &mut TcpStream, status: &str, headers: &[(&str, String)]) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status);
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> io::Result<()> {
+    let mut headers = headers.to_vec();
+    headers.push(("Content-Length", body.len().to_string()));
+    write_head(stream, status, &headers)?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(server: &ReleaseServer, path: &str, range: Option<&str>) -> reqwest::blocking::Response {
+        let mut request = reqwest::blocking::Client::new().get(server.url(path));
+        if let Some(range) = range {
+            request = request.header(reqwest::header::RANGE, range);
+        }
+        request.send().unwrap()
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-", 10), Some((0, 10)));
+        assert_eq!(parse_range("bytes=2-4", 10), Some((2, 5)));
+        assert_eq!(parse_range("bytes=2-100", 10), Some((2, 10)));
+        assert_eq!(parse_range("bytes=-3", 10), Some((7, 10)));
+        assert_eq!(parse_range("bytes=10-", 10), None);
+        assert_eq!(parse_range("bytes=4-2", 10), None);
+        assert_eq!(parse_range("items=0-", 10), None);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let root = Path::new("releases");
+        assert_eq!(
+            resolve(root, "/v1//hello.zip"),
+            Some(root.join("v1").join("hello.zip"))
+        );
+        assert_eq!(resolve(root, "/v1/../../secret"), None);
+        assert_eq!(resolve(root, "/v1/..\\secret"), None);
+        assert_eq!(
+            resolve(root, "/hello%201.0.zip"),
+            Some(root.join("hello 1.0.zip"))
+        );
+        assert_eq!(resolve(root, "/v1/%2e%2e/%2E%2E/secret"), None);
+        assert_eq!(resolve(root, "/v1%2F..%2F..%2Fsecret"), None);
+        assert_eq!(resolve(root, "/v1%5c..%5csecret"), None);
+        assert_eq!(resolve(root, "/hello%2"), None);
+        assert_eq!(resolve(root, "/hello%zz"), None);
+        assert_eq!(resolve(root, "/hello%+1"), None);
+    }
+
+    #[test]
+    fn test_serve() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("v1")).unwrap();
+        fs::write(dir.path().join("v1/hello.zip"), b"0123456789").unwrap();
+        let server = ReleaseServer::bind(dir.path(), "127.0.0.1:0").unwrap();
+
+        let resp = get(&server, "v1/hello.zip", None);
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        assert_eq!(resp.content_length(), Some(10));
+        assert_eq!(resp.bytes().unwrap().as_ref(), b"0123456789");
+
+        let resp = get(&server, "v1/hello.zip", Some("bytes=4-"));
+        assert_eq!(resp.status(), reqwest::StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            resp.headers()[reqwest::header::CONTENT_RANGE],
+            "bytes 4-9/10"
+        );
+        assert_eq!(resp.bytes().unwrap().as_ref(), b"456789");
+        let resp = get(&server, "v1/hello.zip", Some("bytes=10-"));
+        assert_eq!(resp.status(), reqwest::StatusCode::RANGE_NOT_SATISFIABLE);
+
+        assert_eq!(
+            get(&server, "v1/missing.zip", None).status(),
+            reqwest::StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            server.requests()[1],
+            Request {
+                path: "/v1/hello.zip".to_string(),
+                range: Some("bytes=4-".to_string()),
+                if_none_match: None,
+            }
+        );
+
+        let etag = get(&server, "v1/hello.zip", None).headers()[reqwest::header::ETAG].clone();
+        let resp = reqwest::blocking::Client::new()
+            .get(server.url("v1/hello.zip"))
+            .header(reqwest::header::IF_NONE_MATCH, etag)
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), reqwest::StatusCode::NOT_MODIFIED);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_serve_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("releases");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("hello.zip"), b"0123456789").unwrap();
+        fs::write(dir.path().join("secret"), b"secret").unwrap();
+        std::os::unix::fs::symlink("hello.zip", root.join("latest.zip")).unwrap();
+        std::os::unix::fs::symlink("../secret", root.join("leak")).unwrap();
+        let server = ReleaseServer::bind(&root, "127.0.0.1:0").unwrap();
+
+        let resp = get(&server, "latest.zip", None);
+        assert_eq!(resp.status(), reqwest::StatusCode::OK);
+        assert_eq!(resp.bytes().unwrap().as_ref(), b"0123456789");
+        assert_eq!(
+            get(&server, "leak", None).status(),
+            reqwest::StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get(&server, "%6C%65ak", None).status(),
+            reqwest::StatusCode::NOT_FOUND
+        );
+    }
+
+    #[test]
+    fn test_faults() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("hello.zip"), b"0123456789").unwrap();
+        let server = ReleaseServer::bind(dir.path(), "127.0.0.1:0").unwrap();
+
+        server.fault_next(Fault::ServerError);
+        assert_eq!(
+            get(&server, "hello.zip", None).status(),
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(
+            get(&server, "hello.zip", None).status(),
+            reqwest::StatusCode::OK
+        );
+
+        server.fault_path("/hello.zip", Fault::ServerError);
+        assert_eq!(
+            get(&server, "missing.zip", None).status(),
+            reqwest::StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get(&server, "hello.zip", None).status(),
+            reqwest::StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(
+            get(&server, "hello.zip", None).status(),
+            reqwest::StatusCode::OK
+        );
+
+        server.fault_always(Fault::Truncate(4));
+        let resp = get(&server, "hello.zip", None);
+        assert_eq!(resp.content_length(), Some(10));
+        assert!(resp.bytes().is_err());
+        server.clear_faults();
+
+        server.fault_next(Fault::Throttle(20));
+        let started_at = std::time::Instant::now();
+        assert_eq!(
+            get(&server, "hello.zip", None).bytes().unwrap().as_ref(),
+            b"0123456789"
+        );
+        assert!(started_at.elapsed() >= Duration::from_millis(400));
+    }
+}
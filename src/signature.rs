@@ -0,0 +1,108 @@
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+#[cfg(feature = "cli")]
+use ed25519_dalek::{Signer, SigningKey};
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+// a publisher key the client is willing to accept manifests from. several keys
+// can be trusted at once so that the publisher can rotate keys without cutting
+// off clients that only know the old one.
+#[derive(Debug, Clone)]
+pub struct TrustedKey {
+    key_id: String,
+    key: VerifyingKey,
+}
+
+impl TrustedKey {
+    pub fn new(key_id: impl Into<String>, public_key: &[u8]) -> Result<Self, Error> {
+        let key_id = key_id.into();
+        let bytes: [u8; 32] = public_key
+            .try_into()
+            .map_err(|_| Error::InvalidPublicKey(key_id.clone()))?;
+        let key = VerifyingKey::from_bytes(&bytes)
+            .map_err(|_| Error::InvalidPublicKey(key_id.clone()))?;
+        Ok(TrustedKey { key_id, key })
+    }
+
+    pub fn from_hex(key_id: impl Into<String>, public_key: &str) -> Result<Self, Error> {
+        let key_id = key_id.into();
+        let bytes =
+            hex::decode(public_key.trim()).map_err(|_| Error::InvalidPublicKey(key_id.clone()))?;
+        Self::new(key_id, &bytes)
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+}
+
+// the detached signature published next to a manifest, at `<publishing url>.sig`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestSignature {
+    #[serde(rename = "keyId")]
+    pub key_id: String,
+    pub signature: String,
+}
+
+impl ManifestSignature {
+    pub fn signature_url(publishing_url: &str) -> String {
+        format!("{}.sig", publishing_url)
+    }
+
+    #[cfg(feature = "cli")]
+    pub fn sign(key_id: impl Into<String>, signing_key: &SigningKey, manifest: &[u8]) -> Self {
+        ManifestSignature {
+            key_id: key_id.into(),
+            signature: hex::encode(signing_key.sign(manifest).to_bytes()),
+        }
+    }
+
+    pub fn verify(&self, trusted_keys: &[TrustedKey], manifest: &[u8]) -> Result<(), Error> {
+        let trusted_key = trusted_keys
+            .iter()
+            .find(|key| key.key_id == self.key_id)
+            .ok_or_else(|| Error::UntrustedKey(self.key_id.clone()))?;
+        let bytes: [u8; 64] = hex::decode(self.signature.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| Error::InvalidSignature(self.key_id.clone()))?;
+        trusted_key
+            .key
+            .verify(manifest, &Signature::from_bytes(&bytes))
+            .map_err(|_| Error::InvalidSignature(self.key_id.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(secret: u8, key_id: &str, manifest: &[u8]) -> (TrustedKey, ManifestSignature) {
+        let signing_key = SigningKey::from_bytes(&[secret; 32]);
+        let trusted_key = TrustedKey::new(key_id, signing_key.verifying_key().as_bytes()).unwrap();
+        (
+            trusted_key,
+            ManifestSignature::sign(key_id, &signing_key, manifest),
+        )
+    }
+
+    #[test]
+    fn test_verify() {
+        let manifest = b"version: 1.2.3";
+        let (old_key, _) = sign(1, "2021", manifest);
+        let (new_key, signature) = sign(2, "2022", manifest);
+
+        signature
+            .verify(&[old_key.clone(), new_key.clone()], manifest)
+            .unwrap();
+        match signature.verify(std::slice::from_ref(&old_key), manifest) {
+            Err(Error::UntrustedKey(key_id)) => assert_eq!(key_id, "2022"),
+            _ => panic!("expected untrusted key"),
+        }
+        match signature.verify(&[old_key, new_key], b"version: 6.6.6") {
+            Err(Error::InvalidSignature(_)) => {}
+            _ => panic!("expected invalid signature"),
+        }
+    }
+}
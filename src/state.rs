@@ -0,0 +1,267 @@
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{checker::CachedManifest, data::VersionTag, policy::UpdatePolicy, Error};
+
+const STATE_FILE: &str = "state.yaml";
+// installs kept in `installed_versions`
+const HISTORY_LEN: usize = 20;
+
+// everything the updater has to remember between two launches
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdaterState {
+    // identifies this install in staged rollouts
+    #[serde(rename = "clientId", default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    // skipped versions used to be kept apart from the policy, they are moved
+    // into it on load
+    #[serde(rename = "skippedVersions", default, skip_serializing)]
+    skipped_versions: Vec<VersionTag>,
+    #[serde(
+        rename = "pendingHealthCheck",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub pending_health_check: Option<PendingHealthCheck>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub policy: UpdatePolicy,
+    // unix time of the last check that reached the publisher
+    #[serde(rename = "lastCheck", default, skip_serializing_if = "Option::is_none")]
+    pub last_check: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<CachedManifest>,
+    // oldest first
+    #[serde(
+        rename = "installedVersions",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub installed_versions: Vec<InstalledVersion>,
+}
+
+fn is_default(policy: &UpdatePolicy) -> bool {
+    *policy == UpdatePolicy::default()
+}
+
+// an installed version that has not confirmed it works yet
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PendingHealthCheck {
+    pub version: VersionTag,
+    #[serde(rename = "previousVersion")]
+    pub previous_version: VersionTag,
+    #[serde(rename = "installedAt")]
+    pub installed_at: u64,
+    pub launches: u32,
+    // the version that started this one already counted the launch
+    #[serde(
+        rename = "handedOver",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub handed_over: bool,
+}
+
+// a version the updater installed, or reverted to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct InstalledVersion {
+    pub version: VersionTag,
+    #[serde(rename = "previousVersion")]
+    pub previous_version: VersionTag,
+    #[serde(rename = "installedAt")]
+    pub installed_at: u64,
+}
+
+impl UpdaterState {
+    pub fn load(staging_dir: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = staging_dir.as_ref().join(STATE_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(&path).map_err(|err| Error::CommonFileError {
+            message: format!("failed to read updater state `{:?}`", &path),
+            source: err,
+        })?;
+        let mut state: Self = serde_yaml::from_str(&text)?;
+        for version in std::mem::take(&mut state.skipped_versions) {
+            state.policy.skip(version);
+        }
+        Ok(state)
+    }
+
+    pub fn save(&self, staging_dir: impl AsRef<Path>) -> Result<(), Error> {
+        let staging_dir = staging_dir.as_ref();
+        fs::create_dir_all(staging_dir).map_err(|err| Error::CommonFileError {
+            message: format!("failed to create staging directory `{:?}`", staging_dir),
+            source: err,
+        })?;
+        let path = staging_dir.join(STATE_FILE);
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, serde_yaml::to_string(self)?)
+            .and_then(|_| fs::rename(&temp_path, &path))
+            .map_err(|err| Error::CommonFileError {
+                message: format!("failed to write updater state `{:?}`", &path),
+                source: err,
+            })
+    }
+
+    // reloads the state right before changing it, and saves it if it changed.
+    // changes made in the meantime, e.g. through `Reactor::set_update_policy`
+    // while an update is downloaded, are kept.
+    pub fn update<T>(
+        state_dir: impl AsRef<Path>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> Result<T, Error> {
+        let state_dir = state_dir.as_ref();
+        let mut state = Self::load(state_dir)?;
+        let loaded = state.clone();
+        let result = f(&mut state);
+        if state != loaded {
+            state.save(state_dir)?;
+        }
+        Ok(result)
+    }
+
+    // returns whether a new client id had to be created
+    pub fn ensure_client_id(&mut self) -> Result<bool, Error> {
+        if self.client_id.is_some() {
+            return Ok(false);
+        }
+        self.client_id = Some(random_id()?);
+        Ok(true)
+    }
+
+    pub fn record_install(&mut self, version: VersionTag, previous_version: VersionTag) {
+        self.installed_versions.push(InstalledVersion {
+            version,
+            previous_version,
+            installed_at: unix_time(),
+        });
+        let excess = self.installed_versions.len().saturating_sub(HISTORY_LEN);
+        self.installed_versions.drain(..excess);
+    }
+}
+
+// 128 random bits from the os as hex
+fn random_id() -> Result<String, Error> {
+    let mut bytes = [0; 16];
+    getrandom::getrandom(&mut bytes).map_err(Error::RandomnessError)?;
+    Ok(hex::encode(bytes))
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            UpdaterState::load(dir.path()).unwrap(),
+            UpdaterState::default()
+        );
+
+        let mut state = UpdaterState::default();
+        state.policy.skip(VersionTag::new(1, 2, 3));
+        state.pending_health_check = Some(PendingHealthCheck {
+            version: VersionTag::new(1, 2, 3),
+            previous_version: VersionTag::new(1, 2, 2),
+            installed_at: 114514,
+            launches: 1,
+            handed_over: true,
+        });
+        assert!(state.ensure_client_id().unwrap());
+        let client_id = state.client_id.clone().unwrap();
+        assert_eq!(client_id.len(), 32);
+        assert!(!state.ensure_client_id().unwrap());
+        state.save(dir.path()).unwrap();
+
+        let mut state = UpdaterState::load(dir.path()).unwrap();
+        assert_eq!(state.policy.skipped, vec![VersionTag::new(1, 2, 3)]);
+        let pending = state.pending_health_check.as_ref().unwrap();
+        assert_eq!(pending.launches, 1);
+        assert!(pending.handed_over);
+        assert!(!state.ensure_client_id().unwrap());
+        assert_eq!(state.client_id, Some(client_id.clone()));
+
+        assert_eq!(state.last_check, None);
+        for patch in 0..25 {
+            state.record_install(
+                VersionTag::new(1, 0, patch + 1),
+                VersionTag::new(1, 0, patch),
+            );
+        }
+        state.last_check = Some(114514);
+        state.manifest = Some(CachedManifest {
+            url: "http://127.0.0.1:8080/hello.yaml".to_string(),
+            etag: Some("\"abc\"".to_string()),
+            text: "channels: {}".to_string(),
+            signature: None,
+        });
+        state.save(dir.path()).unwrap();
+
+        let state = UpdaterState::load(dir.path()).unwrap();
+        assert_eq!(state.installed_versions.len(), HISTORY_LEN);
+        assert_eq!(
+            state.installed_versions.last().unwrap().version,
+            VersionTag::new(1, 0, 25)
+        );
+        assert_eq!(
+            state.installed_versions.first().unwrap().previous_version,
+            VersionTag::new(1, 0, 5)
+        );
+        assert_eq!(state.last_check, Some(114514));
+        assert_eq!(state.manifest.unwrap().etag.as_deref(), Some("\"abc\""));
+
+        let mut other = UpdaterState::default();
+        other.ensure_client_id().unwrap();
+        assert_ne!(other.client_id, Some(client_id));
+    }
+
+    #[test]
+    fn test_load_skipped_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join(STATE_FILE),
+            "skippedVersions: [1.2.3]\npolicy:\n  skipped: [1.2.4]\n",
+        )
+        .unwrap();
+        let state = UpdaterState::load(dir.path()).unwrap();
+        assert_eq!(
+            state.policy.skipped,
+            vec![VersionTag::new(1, 2, 4), VersionTag::new(1, 2, 3)]
+        );
+        state.save(dir.path()).unwrap();
+        let text = fs::read_to_string(dir.path().join(STATE_FILE)).unwrap();
+        assert!(!text.contains("skippedVersions"), "{}", text);
+    }
+
+    #[test]
+    fn test_update() {
+        let dir = tempfile::tempdir().unwrap();
+        // nothing is written while nothing changes
+        UpdaterState::update(dir.path(), |state| state.last_check).unwrap();
+        assert!(!dir.path().join(STATE_FILE).exists());
+        // e.g. the settings screen changes the policy while a check is running
+        UpdaterState::update(dir.path(), |state| {
+            state.policy.skip(VersionTag::new(2, 0, 0))
+        })
+        .unwrap();
+        UpdaterState::update(dir.path(), |state| state.last_check = Some(114514)).unwrap();
+
+        let state = UpdaterState::load(dir.path()).unwrap();
+        assert_eq!(state.policy.skipped, vec![VersionTag::new(2, 0, 0)]);
+        assert_eq!(state.last_check, Some(114514));
+    }
+}
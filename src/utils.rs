@@ -1,12 +1,12 @@
 use std::{
-    cmp::min,
-    env, fs,
-    io::{self, BufRead, Write},
-    path::{Path, PathBuf},
+    env,
+    fmt::Display,
+    fs, io,
+    path::{Component, Path},
+    time::SystemTime,
 };
 
-use indicatif::ProgressBar;
-use log::trace;
+use serde::{Deserialize, Serialize};
 
 use crate::Error;
 
@@ -19,176 +19,307 @@ pub fn get_executable_file_name(name: &str) -> Result<String, Error> {
     }
 }
 
-pub fn download_file(url: &str, dest: impl AsRef<Path>) -> Result<(), Error> {
-    trace!(
-        "start downloading file from `{}` to `{:?}`",
-        url,
-        dest.as_ref()
-    );
-    // let resp = reqwest::blocking::get(url)?.bytes()?;
-    // let mut file = std::fs::File::create(dest.as_ref()).map_err(|err| Error::CommonFileError {
-    //     message: format!("failed to create file `{:?}`", dest.as_ref()),
-    //     source: err,
-    // })?;
-    // file.write_all(&resp)
-    //     .map_err(|err| Error::CommonFileError {
-    //         message: format!("failed to write file `{:?}`", dest.as_ref()),
-    //         source: err,
-    //     })?;
-
-    let resp = reqwest::blocking::Client::new().get(url).send()?;
-    let size = resp
-        .headers()
-        .get(reqwest::header::CONTENT_LENGTH)
-        .map(|val| {
-            val.to_str()
-                .map(|s| s.parse::<u64>().unwrap_or(0))
-                .unwrap_or(0)
-        })
-        .unwrap_or(0);
-    if !resp.status().is_success() {
-        return Err(Error::NetError(format!(
-            "request failed with status: {:?}",
-            resp.status()
-        )));
-    }
-    let mut src = io::BufReader::new(resp);
-    let mut downloaded = 0;
-    let bar = {
-        let pb = ProgressBar::new(size);
-        pb
-    };
+// splits a versioned executable name such as `hello-1.2.3-beta.1.exe` into its
+// name and version parts
+pub fn split_versioned_file_name(file_name: &str) -> Option<(&str, &str)> {
+    let stem = file_name
+        .strip_suffix(env::consts::EXE_SUFFIX)
+        .unwrap_or(file_name);
+    stem.split_once('-')
+}
 
-    let mut file = std::fs::File::create(dest.as_ref()).map_err(|err| Error::CommonFileError {
-        message: format!("failed to create file `{:?}`", dest.as_ref()),
-        source: err,
-    })?;
-
-    loop {
-        let n = {
-            let buf = src.fill_buf()?;
-            file.write_all(&buf)?;
-            buf.len()
-        };
-        if n == 0 {
-            break;
+// whether a symlink at `relative_path` that points to `target` stays inside the
+// directory `relative_path` is relative to
+pub fn is_contained_link(relative_path: &Path, target: &Path) -> bool {
+    let mut depth = relative_path.components().count() as isize - 1;
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => {
+                depth -= 1;
+                if depth < 0 {
+                    return false;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return false,
         }
-        src.consume(n);
-        downloaded = min(downloaded + n as u64, size);
-        bar.set_position(downloaded);
     }
-    bar.finish_with_message("finish downloading");
+    true
+}
 
-    Ok(())
+// whether `relative_path` below `dir` leads through a symlink that already
+// exists, e.g. one extracted earlier from the same archive. the text of a link
+// says nothing about where a chain of links ends up.
+#[cfg(any(
+    feature = "zip",
+    feature = "tar-gz",
+    feature = "tar-xz",
+    feature = "tar-zst"
+))]
+pub fn leads_through_symlink(dir: &Path, relative_path: &Path) -> bool {
+    let mut path = dir.to_path_buf();
+    relative_path.components().any(|component| {
+        path.push(component);
+        fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+    })
 }
 
-pub fn extract_zip(src: impl AsRef<Path>, dest: impl AsRef<Path>) -> Result<(), Error> {
-    let src = src.as_ref();
-    let dest = dest.as_ref();
-    if !dest.exists() {
-        std::fs::create_dir_all(&dest).map_err(|err| Error::CommonFileError {
-            message: format!("failed to create directories `{:?}`", &dest),
-            source: err,
-        })?;
+#[cfg(unix)]
+pub fn symlink(target: impl AsRef<Path>, link: impl AsRef<Path>) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+pub fn symlink(target: impl AsRef<Path>, link: impl AsRef<Path>) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+pub fn set_mtime(path: impl AsRef<Path>, mtime: SystemTime) -> io::Result<()> {
+    #[cfg(unix)]
+    let file = fs::File::open(path)?;
+    #[cfg(not(unix))]
+    let file = fs::OpenOptions::new().write(true).open(path)?;
+    file.set_modified(mtime)
+}
+
+// copies a file or a symlink, keeping its permissions and modification time.
+// an existing symlink at `dest` is replaced instead of written through.
+pub fn copy(src: impl AsRef<Path>, dest: impl AsRef<Path>) -> io::Result<()> {
+    let (src, dest) = (src.as_ref(), dest.as_ref());
+    let metadata = fs::symlink_metadata(src)?;
+    if fs::symlink_metadata(dest).is_ok_and(|dest| dest.file_type().is_symlink()) {
+        fs::remove_file(dest)?;
     }
-    let mut zip =
-        zip::ZipArchive::new(
-            std::fs::File::open(&src).map_err(|err| Error::CommonFileError {
-                message: format!("failed to open downloaded file `{:?}`", &src),
-                source: err,
-            })?,
-        )?;
-    for i in 0..zip.len() {
-        let mut file = zip.by_index(i)?;
-        if file.is_dir() {
-            continue;
-        }
-        if let Some(relative_path) = file.enclosed_name() {
-            let out_path = dest.join(relative_path);
-            let out_dir = out_path.parent().unwrap();
-            if !out_dir.exists() {
-                std::fs::create_dir_all(out_dir).map_err(|err| Error::CommonFileError {
-                    message: format!("failed to extract directories `{:?}`", &out_dir),
-                    source: err,
-                })?;
-            }
-            let mut out_file =
-                std::fs::File::create(&out_path).map_err(|err| Error::CommonFileError {
-                    message: format!("failed to create extracted file slot `{:?}`", &out_path),
-                    source: err,
-                })?;
-            io::copy(&mut file, &mut out_file).map_err(|err| Error::CommonFileError {
-                message: format!("failed to extract file `{:?}`", &out_file),
-                source: err,
-            })?;
-            trace!("unzipped {}", out_path.display());
+    if metadata.file_type().is_symlink() {
+        if fs::symlink_metadata(dest).is_ok() {
+            fs::remove_file(dest)?;
         }
+        return symlink(fs::read_link(src)?, dest);
     }
+    fs::copy(src, dest)?;
+    set_mtime(dest, metadata.modified()?)
+}
 
-    Ok(())
+// the utc date of a unix timestamp as `(year, month, day)`, see
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+#[cfg(feature = "cli")]
+pub fn civil_date(unix_secs: u64) -> (u64, u8, u8) {
+    let days = unix_secs / 86400 + 719468;
+    let (era, day_of_era) = (days / 146097, days % 146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = era * 400 + year_of_era + (month <= 2) as u64;
+    (year, month as u8, day as u8)
 }
 
-pub fn copy<U: AsRef<Path>, V: AsRef<Path>>(from: U, to: V) -> Result<(), std::io::Error> {
-    let mut stack = Vec::new();
-    stack.push(PathBuf::from(from.as_ref()));
+// joins the components of a relative path with `/` on every platform
+pub fn to_slash(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
 
-    let output_root = PathBuf::from(to.as_ref());
-    let input_root = PathBuf::from(from.as_ref()).components().count();
+// matches `/`-separated relative paths. `*` and `?` stay within one path
+// component, `**` spans any number of them. a pattern without `/` matches the
+// file name at any depth.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let (pattern, text) = if pattern.contains('/') {
+        (pattern.trim_start_matches('/'), path)
+    } else {
+        (pattern, path.rsplit('/').next().unwrap_or(path))
+    };
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    match_chars(&pattern, &text)
+}
 
-    while let Some(working_path) = stack.pop() {
-        trace!("process: {:?}", &working_path);
+fn match_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            (0..=text.len()).any(|i| match_chars(rest, &text[i..]))
+                // `**/` also matches no directory at all
+                || (rest.first() == Some(&'/') && match_chars(&rest[1..], text))
+        }
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| match_chars(rest, &text[i..])),
+        ['?', rest @ ..] => {
+            text.first().is_some_and(|&c| c != '/') && match_chars(rest, &text[1..])
+        }
+        [c, rest @ ..] => text.first() == Some(c) && match_chars(rest, &text[1..]),
+    }
+}
 
-        // Generate a relative path
-        let src: PathBuf = working_path.components().skip(input_root).collect();
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TargetInformation {
+    pub arch: String,
+    pub os: String,
+}
 
-        // Create a destination if missing
-        let dest = if src.components().count() == 0 {
-            output_root.clone()
-        } else {
-            output_root.join(&src)
-        };
-        if fs::metadata(&dest).is_err() {
-            trace!(" mkdir: {:?}", dest);
-            fs::create_dir_all(&dest)?;
+impl TargetInformation {
+    pub fn new(arch: impl Into<String>, os: impl Into<String>) -> Self {
+        TargetInformation {
+            arch: normalize_arch(&arch.into()),
+            os: normalize_os(&os.into()),
         }
+    }
 
-        for entry in fs::read_dir(working_path)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                stack.push(path);
-            } else {
-                match path.file_name() {
-                    Some(filename) => {
-                        let dest_path = dest.join(filename);
-                        trace!("  copy: {:?} -> {:?}", &path, &dest_path);
-                        fs::copy(&path, &dest_path)?;
-                    }
-                    None => {
-                        trace!("failed: {:?}", path);
-                    }
-                }
-            }
-        }
+    pub fn current() -> Self {
+        Self::new(env::consts::ARCH, env::consts::OS)
+    }
+
+    // accepts rust target triples such as `x86_64-unknown-linux-gnu`
+    pub fn from_triple(triple: &str) -> Option<Self> {
+        let mut parts = triple.split('-');
+        let arch = parts.next()?;
+        let os = parts.find(|part| {
+            matches!(
+                *part,
+                "linux"
+                    | "windows"
+                    | "darwin"
+                    | "macos"
+                    | "freebsd"
+                    | "netbsd"
+                    | "openbsd"
+                    | "android"
+                    | "ios"
+            )
+        })?;
+        Some(Self::new(arch, os))
+    }
+
+    pub fn matches(&self, arch: Option<&str>, os: Option<&str>) -> bool {
+        arch.is_none_or(|arch| normalize_arch(arch) == self.arch)
+            && os.is_none_or(|os| normalize_os(os) == self.os)
+    }
+}
+
+impl Display for TargetInformation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.arch, self.os)
     }
+}
 
-    Ok(())
+fn normalize_arch(arch: &str) -> String {
+    match arch.to_ascii_lowercase().as_str() {
+        "amd64" | "x64" => "x86_64".to_string(),
+        "arm64" => "aarch64".to_string(),
+        "i386" | "i586" | "i686" => "x86".to_string(),
+        arch => arch.to_string(),
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct TargetInformation<'t> {
-    arch: &'t str,
-    os: &'t str,
+fn normalize_os(os: &str) -> String {
+    match os.to_ascii_lowercase().as_str() {
+        "darwin" | "osx" => "macos".to_string(),
+        "win32" | "win64" => "windows".to_string(),
+        os => os.to_string(),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     #[test]
-    fn test_download_file() {
-        let url =
-            "https://www.google.com/images/branding/googlelogo/1x/googlelogo_color_272x92dp.png";
-        let dest = PathBuf::from("/tmp/googlelogo.png");
-        download_file(url, &dest).unwrap();
+    fn test_split_versioned_file_name() {
+        let file_name = format!("hello-1.2.3-beta.1{}", env::consts::EXE_SUFFIX);
+        assert_eq!(
+            split_versioned_file_name(&file_name),
+            Some(("hello", "1.2.3-beta.1"))
+        );
+        let file_name = format!("hello{}", env::consts::EXE_SUFFIX);
+        assert_eq!(split_versioned_file_name(&file_name), None);
+    }
+
+    #[test]
+    fn test_is_contained_link() {
+        assert!(is_contained_link(
+            Path::new("bin/hello"),
+            Path::new("../lib/hello")
+        ));
+        assert!(is_contained_link(
+            Path::new("hello"),
+            Path::new("./lib/hello")
+        ));
+        assert!(!is_contained_link(
+            Path::new("bin/hello"),
+            Path::new("../../hello")
+        ));
+        assert!(!is_contained_link(
+            Path::new("hello"),
+            Path::new("/usr/bin/hello")
+        ));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("config.yaml", "config.yaml"));
+        assert!(glob_match("*.yaml", "conf/user.yaml"));
+        assert!(!glob_match("/*.yaml", "conf/user.yaml"));
+        assert!(glob_match("conf/*.yaml", "conf/user.yaml"));
+        assert!(!glob_match("conf/*", "conf/sub/user.yaml"));
+        assert!(glob_match("conf/**", "conf/sub/user.yaml"));
+        assert!(glob_match("**/user.yaml", "user.yaml"));
+        assert!(glob_match("conf/**/user.yaml", "conf/a/b/user.yaml"));
+        assert!(glob_match("plugin?.dll", "plugins/plugin1.dll"));
+        assert!(!glob_match("plugin?.dll", "plugin10.dll"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy() {
+        use std::os::unix::prelude::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (src, dest) = (dir.path().join("src"), dir.path().join("dest"));
+        fs::write(&src, "script").unwrap();
+        fs::set_permissions(&src, fs::Permissions::from_mode(0o755)).unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        set_mtime(&src, mtime).unwrap();
+        // must not overwrite the file the old link points to
+        symlink(&src, &dest).unwrap();
+
+        copy(&src, &dest).unwrap();
+        let metadata = fs::symlink_metadata(&dest).unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        assert_eq!(metadata.modified().unwrap(), mtime);
+
+        let (link, copied_link) = (dir.path().join("link"), dir.path().join("copied_link"));
+        symlink("src", &link).unwrap();
+        copy(&link, &copied_link).unwrap();
+        assert_eq!(fs::read_link(&copied_link).unwrap(), Path::new("src"));
+    }
+
+    #[test]
+    fn test_target_information() {
+        let target = TargetInformation::new("x86_64", "linux");
+        assert_eq!(
+            TargetInformation::from_triple("x86_64-unknown-linux-gnu"),
+            Some(target.clone())
+        );
+        assert_eq!(
+            TargetInformation::from_triple("aarch64-apple-darwin"),
+            Some(TargetInformation::new("arm64", "macos"))
+        );
+        assert_eq!(
+            TargetInformation::from_triple("wasm32-unknown-unknown"),
+            None
+        );
+        assert!(target.matches(Some("amd64"), None));
+        assert!(target.matches(None, None));
+        assert!(!target.matches(Some("x86_64"), Some("windows")));
     }
 }
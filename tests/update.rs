@@ -0,0 +1,504 @@
+#![cfg(feature = "zip")]
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use reduction_react::{
+    progress::SilentReporter,
+    publish::{self, Release},
+    restart::Restarter,
+    server::{Fault, ReleaseServer, Request},
+    Error, HealthCheck, Reactor, ReactorBuilder, RetryPolicy, UpdatePolicy,
+};
+use tempfile::TempDir;
+
+// remembers what would have been started instead of replacing the test process
+#[derive(Debug, Clone, Default)]
+struct Restarts(Arc<Mutex<Vec<PathBuf>>>);
+
+impl Restarter for Restarts {
+    fn restart(&self, executable: &Path) -> Result<(), Error> {
+        self.0.lock().unwrap().push(executable.to_path_buf());
+        Ok(())
+    }
+}
+
+// a fake application `hello`, installed in a temp dir and updated from releases
+// published by a local server. its executables are scripts printing the version.
+struct Harness {
+    dir: TempDir,
+    server: ReleaseServer,
+    restarts: Restarts,
+    retry_policy: RetryPolicy,
+    enforce_required_updates: bool,
+    check_interval: Option<Duration>,
+    health_check: Option<HealthCheck>,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("install")).unwrap();
+        fs::create_dir_all(dir.path().join("releases")).unwrap();
+        let server = ReleaseServer::bind(dir.path().join("releases"), "127.0.0.1:0").unwrap();
+        Harness {
+            dir,
+            server,
+            restarts: Restarts::default(),
+            retry_policy: RetryPolicy {
+                initial_backoff: Duration::from_millis(10),
+                ..Default::default()
+            },
+            enforce_required_updates: false,
+            check_interval: None,
+            health_check: None,
+        }
+    }
+
+    fn install_dir(&self) -> PathBuf {
+        self.dir.path().join("install")
+    }
+
+    fn releases_dir(&self) -> PathBuf {
+        self.dir.path().join("releases")
+    }
+
+    fn versioned_exe(version: &str) -> String {
+        format!("hello-{}{}", version, env::consts::EXE_SUFFIX)
+    }
+
+    fn default_exe() -> String {
+        format!("hello{}", env::consts::EXE_SUFFIX)
+    }
+
+    // writes the executable of `version` and the other files of a release
+    fn write_release(dir: &Path, exe: &str, version: &str, files: &[(&str, &str)]) {
+        write(
+            dir.join(exe),
+            &format!("#!/bin/sh\necho hello {}\n", version),
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::prelude::PermissionsExt;
+            fs::set_permissions(dir.join(exe), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        for (path, content) in files {
+            write(dir.join(path), content);
+        }
+    }
+
+    // the state after installing `version` by hand
+    fn install(&self, version: &str, files: &[(&str, &str)]) {
+        Self::write_release(&self.install_dir(), &Self::default_exe(), version, files);
+    }
+
+    // packs and publishes `version` on the stable channel. returns the archive.
+    fn publish(&self, version: &str, files: &[(&str, &str)]) -> PathBuf {
+        let build_dir = self.dir.path().join("build").join(version);
+        Self::write_release(&build_dir, &Self::versioned_exe(version), version, files);
+        let file_name = format!("hello-{}.zip", version);
+        let archive = self.releases_dir().join(&file_name);
+        publish::package(&build_dir, &archive, None).unwrap();
+        publish::add_release(
+            self.releases_dir().join("hello.yaml"),
+            &Release::new("stable", version, &archive, self.server.url(&file_name)),
+        )
+        .unwrap();
+        archive
+    }
+
+    fn reactor(&self, exe: &str, version: &str) -> Reactor {
+        let builder = ReactorBuilder::new()
+            .name("hello")
+            .version(version)
+            .publishing_url(self.server.url("hello.yaml"))
+            .install_dir(self.install_dir())
+            .current_exe(self.install_dir().join(exe))
+            .retry_policy(self.retry_policy)
+            .progress_reporter(SilentReporter)
+            .restarter(self.restarts.clone())
+            .enforce_required_updates(self.enforce_required_updates);
+        let builder = match self.health_check {
+            Some(health_check) => builder.health_check(health_check),
+            None => builder,
+        };
+        match self.check_interval {
+            Some(check_interval) => builder.check_interval(check_interval),
+            None => builder,
+        }
+        .finish()
+        .unwrap()
+    }
+
+    // the requests for the manifest since the server started
+    fn manifest_requests(&self) -> Vec<Request> {
+        self.server
+            .requests()
+            .into_iter()
+            .filter(|request| request.path == "/hello.yaml")
+            .collect()
+    }
+
+    // runs the updater as if `exe` of `version` had just been started
+    fn launch(&self, exe: &str, version: &str) -> Result<(), Error> {
+        self.reactor(exe, version).oneclick()
+    }
+
+    fn restarts(&self) -> Vec<PathBuf> {
+        self.restarts.0.lock().unwrap().clone()
+    }
+
+    fn read(&self, path: &str) -> Option<String> {
+        fs::read_to_string(self.install_dir().join(path)).ok()
+    }
+
+    fn assert_untouched(&self) {
+        assert_eq!(self.read("lib/data.txt").as_deref(), Some("v1"));
+        assert_eq!(self.read(&Self::versioned_exe("2.0.0")), None);
+        assert_eq!(self.read("lib/new.txt"), None);
+        assert!(self.restarts().is_empty());
+        assert!(!self
+            .install_dir()
+            .join(".reduction_react/journal.yaml")
+            .exists());
+    }
+}
+
+fn write(path: impl AsRef<Path>, content: &str) {
+    let path = path.as_ref();
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+// the output of a started executable
+#[cfg(unix)]
+fn run(executable: &Path) -> String {
+    let output = std::process::Command::new(executable).output().unwrap();
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+#[test]
+fn test_update_cycle() {
+    let harness = Harness::new();
+    harness.install("1.0.0", &[("lib/data.txt", "v1")]);
+    harness.publish("1.0.0", &[("lib/data.txt", "v1")]);
+    harness.publish("2.0.0", &[("lib/data.txt", "v2"), ("lib/new.txt", "new")]);
+    let v2 = Harness::versioned_exe("2.0.0");
+
+    // the old version installs the update and starts the new executable
+    harness.launch(&Harness::default_exe(), "1.0.0").unwrap();
+    assert_eq!(harness.read("lib/data.txt").as_deref(), Some("v2"));
+    assert_eq!(harness.read("lib/new.txt").as_deref(), Some("new"));
+    let started = harness.install_dir().join(&v2).canonicalize().unwrap();
+    assert_eq!(harness.restarts(), vec![started.clone()]);
+    #[cfg(unix)]
+    assert_eq!(run(&started), "hello 2.0.0");
+
+    // the new version makes itself the default executable
+    harness.launch(&v2, "2.0.0").unwrap();
+    let default_exe = harness.install_dir().join(Harness::default_exe());
+    assert_eq!(
+        harness.restarts().last(),
+        Some(&default_exe.canonicalize().unwrap())
+    );
+    #[cfg(unix)]
+    assert_eq!(run(&default_exe), "hello 2.0.0");
+
+    // and cleans up after itself once started as the default
+    harness.launch(&Harness::default_exe(), "2.0.0").unwrap();
+    assert_eq!(harness.restarts().len(), 2);
+    assert_eq!(harness.read(&v2), None);
+    assert_eq!(harness.read("lib/data.txt").as_deref(), Some("v2"));
+}
+
+#[test]
+fn test_hash_mismatch() {
+    let harness = Harness::new();
+    harness.install("1.0.0", &[("lib/data.txt", "v1")]);
+    let archive = harness.publish("2.0.0", &[("lib/data.txt", "v2"), ("lib/new.txt", "new")]);
+    // the archive changed after the manifest was written
+    publish::package(harness.dir.path().join("build/2.0.0/lib"), &archive, None).unwrap();
+
+    let result = harness.launch(&Harness::default_exe(), "1.0.0");
+    assert!(matches!(result, Err(Error::HashMismatch { .. })));
+    harness.assert_untouched();
+    assert!(!harness
+        .install_dir()
+        .join(".reduction_react/download")
+        .exists());
+}
+
+#[test]
+fn test_file_mismatch() {
+    let harness = Harness::new();
+    harness.install("1.0.0", &[("lib/data.txt", "v1")]);
+    harness.publish("2.0.0", &[("lib/data.txt", "v2"), ("lib/new.txt", "new")]);
+    // the archive does not hold the files published with the release
+    let manifest_path = harness.releases_dir().join("hello.yaml");
+    let mut manifest: serde_yaml::Value =
+        serde_yaml::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    let files = &mut manifest["channels"]["stable"][0]["files"];
+    assert!(files["lib/new.txt"].is_string());
+    files["lib/data.txt"] = files["lib/new.txt"].clone();
+    fs::write(&manifest_path, serde_yaml::to_string(&manifest).unwrap()).unwrap();
+
+    let result = harness.launch(&Harness::default_exe(), "1.0.0");
+    assert!(
+        matches!(&result, Err(Error::FileMismatch { path, .. }) if path == "lib/data.txt"),
+        "{:?}",
+        result
+    );
+    harness.assert_untouched();
+    assert!(!harness
+        .install_dir()
+        .join(".reduction_react/download")
+        .exists());
+}
+
+#[test]
+fn test_truncated_download() {
+    let mut harness = Harness::new();
+    harness.retry_policy = RetryPolicy::no_retry();
+    harness.install("1.0.0", &[("lib/data.txt", "v1")]);
+    harness.publish("2.0.0", &[("lib/data.txt", "v2"), ("lib/new.txt", "new")]);
+    // the manifest arrives in full, the archive is cut off
+    harness
+        .server
+        .fault_path("hello-2.0.0.zip", Fault::Truncate(100));
+
+    let result = harness.launch(&Harness::default_exe(), "1.0.0");
+    assert!(result.is_err());
+    harness.assert_untouched();
+    let download = harness.install_dir().join(".reduction_react/download");
+    assert_eq!(fs::metadata(&download).unwrap().len(), 100);
+
+    // the next start resumes the download where it stopped
+    harness.server.clear_faults();
+    harness.launch(&Harness::default_exe(), "1.0.0").unwrap();
+    assert_eq!(harness.read("lib/data.txt").as_deref(), Some("v2"));
+    let request = harness.server.requests().pop().unwrap();
+    assert_eq!(request.path, "/hello-2.0.0.zip");
+    assert_eq!(request.range.as_deref(), Some("bytes=100-"));
+}
+
+#[test]
+fn test_copy_failure() {
+    let harness = Harness::new();
+    harness.install(
+        "1.0.0",
+        &[("lib/data.txt", "v1"), ("lib/new.txt/user.txt", "user")],
+    );
+    harness.publish("2.0.0", &[("lib/data.txt", "v2"), ("lib/new.txt", "new")]);
+
+    // a directory is in the way of `lib/new.txt`, so copying fails halfway
+    let result = harness.launch(&Harness::default_exe(), "1.0.0");
+    assert!(matches!(result, Err(Error::CommonFileError { .. })));
+    assert_eq!(harness.read("lib/data.txt").as_deref(), Some("v1"));
+    assert_eq!(
+        harness.read("lib/new.txt/user.txt").as_deref(),
+        Some("user")
+    );
+    assert_eq!(harness.read(&Harness::versioned_exe("2.0.0")), None);
+    assert!(harness.restarts().is_empty());
+    assert!(!harness
+        .install_dir()
+        .join(".reduction_react/journal.yaml")
+        .exists());
+}
+
+#[test]
+fn test_required_update() {
+    let mut harness = Harness::new();
+    harness.retry_policy = RetryPolicy::no_retry();
+    harness.install("1.0.0", &[("lib/data.txt", "v1")]);
+    harness.publish("2.0.0", &[("lib/data.txt", "v2"), ("lib/new.txt", "new")]);
+    publish::set_minimum_version(harness.releases_dir().join("hello.yaml"), "2.0.0").unwrap();
+
+    // the manifest arrives, the archive does not
+    harness
+        .server
+        .fault_path("hello-2.0.0.zip", Fault::ServerError);
+    let result = harness.launch(&Harness::default_exe(), "1.0.0");
+    assert!(matches!(result, Err(Error::NetError(_))), "{:?}", result);
+
+    harness.enforce_required_updates = true;
+    harness
+        .server
+        .fault_path("hello-2.0.0.zip", Fault::ServerError);
+    let result = harness.launch(&Harness::default_exe(), "1.0.0");
+    assert!(matches!(
+        result,
+        Err(Error::UpdateRequired {
+            source: Some(_),
+            ..
+        })
+    ));
+    harness.assert_untouched();
+
+    // without the manifest, the one of the last check still applies
+    harness.server.fault_path("hello.yaml", Fault::ServerError);
+    let result = harness.launch(&Harness::default_exe(), "1.0.0");
+    assert!(matches!(
+        result,
+        Err(Error::UpdateRequired {
+            source: Some(_),
+            ..
+        })
+    ));
+
+    harness.launch(&Harness::default_exe(), "1.0.0").unwrap();
+    assert_eq!(harness.read("lib/data.txt").as_deref(), Some("v2"));
+}
+
+#[test]
+fn test_update_policy() {
+    let harness = Harness::new();
+    harness.install("1.0.0", &[("lib/data.txt", "v1")]);
+    harness.publish("2.0.0", &[("lib/data.txt", "v2"), ("lib/new.txt", "new")]);
+
+    // e.g. chosen on the settings screen of the application
+    let reactor = harness.reactor(&Harness::default_exe(), "1.0.0");
+    let mut policy = reactor.update_policy().unwrap();
+    assert_eq!(policy, UpdatePolicy::default());
+    policy.pin = Some("~1.0".parse().unwrap());
+    reactor.set_update_policy(policy).unwrap();
+    harness.launch(&Harness::default_exe(), "1.0.0").unwrap();
+    harness.assert_untouched();
+
+    let mut policy = reactor.update_policy().unwrap();
+    policy.pin = None;
+    policy.skip("2.0.0".parse().unwrap());
+    reactor.set_update_policy(policy).unwrap();
+    harness.launch(&Harness::default_exe(), "1.0.0").unwrap();
+    harness.assert_untouched();
+
+    reactor.set_update_policy(UpdatePolicy::default()).unwrap();
+    harness.launch(&Harness::default_exe(), "1.0.0").unwrap();
+    assert_eq!(harness.read("lib/data.txt").as_deref(), Some("v2"));
+}
+
+#[test]
+fn test_check_interval() {
+    let mut harness = Harness::new();
+    harness.install("1.0.0", &[("lib/data.txt", "v1")]);
+    harness.publish("1.0.0", &[("lib/data.txt", "v1")]);
+
+    // the second check only asks whether the manifest changed
+    harness.launch(&Harness::default_exe(), "1.0.0").unwrap();
+    harness.launch(&Harness::default_exe(), "1.0.0").unwrap();
+    let requests = harness.manifest_requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].if_none_match, None);
+    assert!(requests[1].if_none_match.is_some());
+    let reactor = harness.reactor(&Harness::default_exe(), "1.0.0");
+    assert!(reactor.last_check().unwrap().is_some());
+
+    // launches within the interval do not check at all
+    harness.publish("2.0.0", &[("lib/data.txt", "v2"), ("lib/new.txt", "new")]);
+    harness.check_interval = Some(Duration::from_secs(3600));
+    harness.launch(&Harness::default_exe(), "1.0.0").unwrap();
+    assert_eq!(harness.manifest_requests().len(), 2);
+    harness.assert_untouched();
+
+    harness.check_interval = None;
+    harness.launch(&Harness::default_exe(), "1.0.0").unwrap();
+    assert_eq!(harness.read("lib/data.txt").as_deref(), Some("v2"));
+    let installed = reactor.installed_versions().unwrap();
+    assert_eq!(installed.len(), 1);
+    assert_eq!(installed[0].version.to_string(), "2.0.0");
+    assert_eq!(installed[0].previous_version.to_string(), "1.0.0");
+}
+
+#[test]
+fn test_failing_start() {
+    let mut harness = Harness::new();
+    harness.health_check = Some(HealthCheck {
+        max_launches: 2,
+        timeout: None,
+    });
+    harness.install("1.0.0", &[("lib/data.txt", "v1")]);
+    harness.publish("1.0.0", &[("lib/data.txt", "v1")]);
+    harness.publish("2.0.0", &[("lib/data.txt", "v2"), ("lib/new.txt", "new")]);
+    let v2 = harness.install_dir().join(Harness::versioned_exe("2.0.0"));
+
+    // the new version crashes before it gets to call `oneclick`, so the user
+    // starts the old default executable again
+    harness.launch(&Harness::default_exe(), "1.0.0").unwrap();
+    assert_eq!(harness.read("lib/data.txt").as_deref(), Some("v2"));
+    harness.launch(&Harness::default_exe(), "1.0.0").unwrap();
+    assert_eq!(harness.restarts().len(), 2);
+    assert!(harness
+        .restarts()
+        .iter()
+        .all(|started| started.ends_with(v2.file_name().unwrap())));
+
+    // it ran out of launches and is neither started nor installed again
+    harness.launch(&Harness::default_exe(), "1.0.0").unwrap();
+    assert_eq!(harness.restarts().len(), 2);
+    assert!(!v2.exists());
+    assert_eq!(harness.read("lib/data.txt").as_deref(), Some("v1"));
+    assert_eq!(harness.read("lib/new.txt"), None);
+    let reactor = harness.reactor(&Harness::default_exe(), "1.0.0");
+    assert_eq!(reactor.installed_versions().unwrap().len(), 2);
+}
+
+// a patch in the format of `delta::apply` that rewrites the common prefix and
+// appends the rest
+fn naive_diff(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let common = old.len().min(new.len());
+    let mut patch = vec![];
+    for value in [common, new.len() - common, 0] {
+        patch.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+    patch.extend(
+        new[..common]
+            .iter()
+            .zip(&old[..common])
+            .map(|(new, old)| new.wrapping_sub(*old)),
+    );
+    patch.extend_from_slice(&new[common..]);
+    patch
+}
+
+#[test]
+fn test_patch_update() {
+    let harness = Harness::new();
+    harness.install("0.9.0", &[("lib/data.txt", "v0")]);
+    let v1 = harness.publish("1.0.0", &[("lib/data.txt", "v1")]);
+    // the archive of an installed version is kept to be patched later
+    harness.launch(&Harness::default_exe(), "0.9.0").unwrap();
+    assert_eq!(harness.read("lib/data.txt").as_deref(), Some("v1"));
+
+    let v2 = harness.publish("2.0.0", &[("lib/data.txt", "v2"), ("lib/new.txt", "new")]);
+    let patch = harness.releases_dir().join("hello-1.0.0-2.0.0.patch");
+    fs::write(
+        &patch,
+        naive_diff(&fs::read(&v1).unwrap(), &fs::read(&v2).unwrap()),
+    )
+    .unwrap();
+    let manifest_path = harness.releases_dir().join("hello.yaml");
+    let mut manifest: serde_yaml::Value =
+        serde_yaml::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    manifest["channels"]["stable"][0]["patches"] = serde_yaml::from_str(&format!(
+        "[{{from: 1.0.0, to: 2.0.0, hash: '{}', downloadUrl: '{}'}}]",
+        publish::hash(&patch, "sha256").unwrap(),
+        harness.server.url("hello-1.0.0-2.0.0.patch")
+    ))
+    .unwrap();
+    fs::write(&manifest_path, serde_yaml::to_string(&manifest).unwrap()).unwrap();
+
+    let checked = harness.server.requests().len();
+    harness.launch(&Harness::default_exe(), "1.0.0").unwrap();
+    assert_eq!(harness.read("lib/data.txt").as_deref(), Some("v2"));
+    assert_eq!(harness.read("lib/new.txt").as_deref(), Some("new"));
+    let paths = harness.server.requests()[checked..]
+        .iter()
+        .map(|request| request.path.clone())
+        .collect::<Vec<_>>();
+    assert!(paths.contains(&"/hello-1.0.0-2.0.0.patch".to_string()));
+    assert!(!paths.contains(&"/hello-2.0.0.zip".to_string()));
+}